@@ -0,0 +1,85 @@
+// Compares searching candidates whose slow-to-score ones are spread evenly
+// with searching the same candidates with the slow ones all together, which
+// leaves threads searching equal shares waiting on the one with them all
+//
+// Each is searched both by stealing chunks of candidates on the pool and by
+// splitting the candidates into one equal slice per thread, as searches did
+// before.
+//
+// Run with `cargo bench --features bench`, optionally giving the parallelism
+// after `--`.
+
+extern crate fzyr;
+
+use std::env;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use fzyr::{search_score, search_score_equal_split, Query, ScoreResults, ScoringConfig};
+
+const CANDIDATE_COUNT: usize = 200_000;
+// One in this many candidates is slow to score
+const SLOW_EVERY: usize = 8;
+const RUNS: usize = 10;
+
+type Search = fn(&Query, &[String], usize, &ScoringConfig) -> ScoreResults;
+
+fn candidate(i: usize) -> String {
+  if i % SLOW_EVERY == 0 {
+    // Matches everywhere, so scoring fills its whole matrix
+    format!("{}{}", "src/search/query_matcher_".repeat(20), i)
+  } else {
+    format!("docs/{}.md", i)
+  }
+}
+
+fn median_time(
+  search: Search,
+  query: &Query,
+  candidates: &[String],
+  parallelism: usize,
+) -> Duration {
+  let config = ScoringConfig::default();
+  let mut times: Vec<Duration> = (0..RUNS)
+    .map(|_| {
+      let start = Instant::now();
+      search(query, candidates, parallelism, &config);
+      start.elapsed()
+    })
+    .collect();
+  times.sort();
+  times[RUNS / 2]
+}
+
+fn main() {
+  let parallelism = env::args()
+    .skip(1)
+    .filter_map(|arg| arg.parse().ok())
+    .next()
+    .unwrap_or_else(|| thread::available_parallelism().map_or(4, |n| n.get()));
+
+  let even: Vec<String> = (0..CANDIDATE_COUNT).map(candidate).collect();
+  let mut skewed = even.clone();
+  skewed.sort_by_key(|candidate| !candidate.starts_with("src"));
+
+  let query = Query::new("sqm");
+  println!(
+    "{} candidates, parallelism {}, median of {} runs",
+    CANDIDATE_COUNT, parallelism, RUNS
+  );
+  let strategies: [(&str, Search); 2] = [
+    ("stealing chunks", search_score),
+    ("equal slices", search_score_equal_split),
+  ];
+  for &(name, search) in &strategies {
+    let even_time = median_time(search, &query, &even, parallelism);
+    let skewed_time = median_time(search, &query, &skewed, parallelism);
+    println!(
+      "{:<16} even: {:>12?}  skewed: {:>12?}  skewed / even: {:.2}",
+      name,
+      even_time,
+      skewed_time,
+      skewed_time.as_secs_f64() / even_time.as_secs_f64()
+    );
+  }
+}
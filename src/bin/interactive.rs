@@ -1,19 +1,33 @@
 extern crate console;
+extern crate unicode_segmentation;
 
 use io;
 use std::io::Write;
+use std::mem;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use self::console::{Key, Style, Term};
+use self::unicode_segmentation::UnicodeSegmentation;
 
 use fzyr::config::SCORE_MIN;
-use fzyr::{search_locate, LocateResult, LocateResults};
+use fzyr::{CancellationToken, Feed, Index, LocateResult, LocateResults, Session};
 
 use super::opts;
 
-pub fn run(candidates: &[&str], options: &opts::Options) -> i32 {
+// Most candidates read from stdin before they're fed to the session
+const BATCH_MAX: usize = 10_000;
+// Longest to wait before feeding the candidates read from stdin so far
+const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+// Reads candidates from stdin while searching them
+pub fn run(options: &opts::Options) -> i32 {
   let mut terminal = Terminal::new(&options.prompt, options.show_scores, options.lines);
+  terminal.graphemes = options.query_options.graphemes;
 
-  if let Err(_) = terminal.run(candidates, options.parallelism) {
+  if terminal.run(options).is_err() {
     eprintln!("Failed to write to stdout");
     1
   } else {
@@ -21,14 +35,93 @@ pub fn run(candidates: &[&str], options: &opts::Options) -> i32 {
   }
 }
 
+enum Event {
+  Key(io::Result<Key>),
+  // Candidates have been fed to the session
+  Fed,
+  // All of stdin has been read
+  Loaded,
+}
+
+// Reads keys and candidates on other threads
+fn read_events(searching: Arc<Mutex<CancellationToken>>, feed: Feed) -> Receiver<Event> {
+  let (sender, receiver) = mpsc::channel();
+  read_candidates(sender.clone(), feed);
+  read_keys(sender, searching);
+  receiver
+}
+
+// Reads keys, cancelling the search in progress as each one is read
+fn read_keys(sender: Sender<Event>, searching: Arc<Mutex<CancellationToken>>) {
+  thread::spawn(move || {
+    let term = Term::stdout();
+    loop {
+      let key = term.read_key();
+      let failed = key.is_err();
+      searching.lock().unwrap().cancel();
+      if sender.send(Event::Key(key)).is_err() || failed {
+        break;
+      }
+    }
+  });
+}
+
+// Reads candidates from stdin and feeds them to the session in batches, so
+// that they're searched while the rest are read, even by a search in progress
+fn read_candidates(sender: Sender<Event>, feed: Feed) {
+  thread::spawn(move || {
+    let stdin = io::stdin();
+    let mut buff = String::with_capacity(opts::DEFLT_STRING_BUFFER_LEN);
+    let mut batch = Vec::new();
+    let mut sent = Instant::now();
+    while let Ok(bytes) = stdin.read_line(&mut buff) {
+      if bytes == 0 {
+        break;
+      }
+      if let Some(candidate) = super::candidate(&buff) {
+        batch.push(candidate.to_string());
+      }
+      buff.clear();
+
+      if batch.len() >= BATCH_MAX || sent.elapsed() >= BATCH_INTERVAL {
+        feed.extend(mem::take(&mut batch));
+        if sender.send(Event::Fed).is_err() {
+          return;
+        }
+        sent = Instant::now();
+      }
+    }
+    feed.extend(batch);
+    let _ = sender.send(Event::Loaded);
+  });
+}
+
+// Returns whether the query changed
+fn edit_query(query: &mut String, key: Key) -> bool {
+  match key {
+    Key::Char(ch) if ch == '\u{08}' || ch == '\u{7f}' => {
+      // Backspace or delete
+      query.pop().is_some()
+    }
+    Key::Char(ch) => {
+      query.push(ch);
+      true
+    }
+    _ => false,
+  }
+}
+
 struct Terminal<'a> {
   result_count: usize,
   max_display_width: usize,
   prompt: &'a str,
   show_scores: bool,
+  // Draw by grapheme cluster rather than by character
+  graphemes: bool,
   drawn_lines: usize,
   term: Term,
   standout: Style,
+  faint: Style,
 }
 
 impl<'a> Terminal<'a> {
@@ -38,52 +131,86 @@ impl<'a> Terminal<'a> {
     Self {
       result_count: max_results.min((size.0 as usize).saturating_sub(1)),
       max_display_width: size.1 as usize,
-      prompt: prompt,
-      show_scores: show_scores,
+      prompt,
+      show_scores,
+      graphemes: false,
       drawn_lines: 0,
-      term: term,
+      term,
       standout: Style::new().reverse(),
+      faint: Style::new().dim(),
     }
   }
 }
 
 impl<'a> Terminal<'a> {
-  fn run(&mut self, candidates: &[&str], parallelism: usize) -> io::Result<()> {
+  fn run(&mut self, options: &opts::Options) -> io::Result<()> {
     let mut query = String::with_capacity(opts::DEFLT_STRING_BUFFER_LEN);
+    let index = Index::new(&options.scoring);
+    let mut session = Session::new(index, &options.query_options, options.parallelism);
+    let mut loading = true;
+
+    // Every key cancels the search in progress, since its results are stale
+    let searching = Arc::new(Mutex::new(CancellationToken::new()));
+    let feed = session.feed();
+    let events = read_events(searching.clone(), feed.clone());
 
     let mut should_search = true;
     loop {
       if should_search {
-        let search_results = search_locate(&query, candidates, parallelism);
-        self.draw(&query, candidates, &search_results)?;
+        let cancel = CancellationToken::new();
+        *searching.lock().unwrap() = cancel.clone();
+        // A cancelled search leaves the last results drawn until the next one
+        should_search = match session.locate_top_cancellable(&query, self.result_count, &cancel) {
+          Ok(search_results) => {
+            let loading = if loading { Some(session.index().len()) } else { None };
+            self.draw(&query, loading, session.index(), &search_results)?;
+            false
+          }
+          Err(_) => true,
+        };
       }
 
-      should_search = match self.term.read_key()? {
-        Key::Char(ch) if ch == '\u{08}' || ch == '\u{7f}' => match query.pop() {
-          // Backspace or delete
-          Some(_) => true,
-          None => false,
-        },
-        Key::Char(ch) => {
-          query.push(ch);
-          true
-        }
-        _ => false,
+      // Apply everything that happened during the search before searching
+      // again
+      let mut event = match events.recv() {
+        Ok(event) => event,
+        Err(_) => return Ok(()),
       };
+      loop {
+        match event {
+          Event::Key(key) => should_search |= edit_query(&mut query, key?),
+          // Unless the last search already found them
+          Event::Fed => should_search |= !feed.is_empty(),
+          Event::Loaded => {
+            loading = false;
+            should_search = true;
+          }
+        }
+        event = match events.try_recv() {
+          Ok(event) => event,
+          Err(_) => break,
+        };
+      }
     }
   }
 
-  fn draw(&mut self, query: &str, candidates: &[&str], results: &LocateResults) -> io::Result<()> {
+  fn draw(
+    &mut self,
+    query: &str,
+    loading: Option<usize>,
+    index: &Index,
+    results: &LocateResults,
+  ) -> io::Result<()> {
     self.clear()?;
-    self.draw_query(query)?;
-    self.draw_results(candidates, results)?;
+    self.draw_query(query, loading)?;
+    self.draw_results(index, results)?;
     Ok(())
   }
 
   fn clear(&mut self) -> io::Result<()> {
     self.term.clear_line()?;
     self.term.clear_last_lines(if self.drawn_lines > 1 {
-      self.drawn_lines.checked_sub(1).unwrap_or(0)
+      self.drawn_lines.saturating_sub(1)
     } else {
       self.drawn_lines
     })?;
@@ -91,19 +218,23 @@ impl<'a> Terminal<'a> {
     Ok(())
   }
 
-  fn draw_query(&mut self, query: &str) -> io::Result<()> {
-    writeln!(
+  fn draw_query(&mut self, query: &str, loading: Option<usize>) -> io::Result<()> {
+    write!(
       self.term,
       "{}{}{}",
       self.prompt,
       query,
       self.standout.apply_to(" "),
     )?;
+    if let Some(count) = loading {
+      write!(self.term, "  {}", self.faint.apply_to(format!("loading {}…", count)))?;
+    }
+    writeln!(self.term)?;
     self.drawn_lines += 1;
     Ok(())
   }
 
-  fn draw_results(&mut self, candidates: &[&str], results: &LocateResults) -> io::Result<()> {
+  fn draw_results(&mut self, index: &Index, results: &LocateResults) -> io::Result<()> {
     // Write the results
     let total_results = results.len().min(self.result_count);
     let mut line_count: usize = 0;
@@ -111,7 +242,7 @@ impl<'a> Terminal<'a> {
       if line_count > 0 {
         self.term.write_line("")?;
       }
-      self.draw_result(candidates, result)?;
+      self.draw_result(index, result)?;
       line_count += 1;
       self.drawn_lines += 1;
     }
@@ -130,7 +261,7 @@ impl<'a> Terminal<'a> {
     self.term.write_line("")
   }
 
-  fn draw_result(&mut self, candidates: &[&str], result: &LocateResult) -> io::Result<()> {
+  fn draw_result(&mut self, index: &Index, result: &LocateResult) -> io::Result<()> {
     let mut spent_width = 0;
 
     if self.show_scores {
@@ -142,18 +273,26 @@ impl<'a> Terminal<'a> {
       spent_width += 8;
     }
 
-    let found = candidates[result.candidate_index];
-    for (i, ch) in found
-      .chars()
-      .take(self.max_display_width - spent_width)
-      .enumerate()
-    {
-      if result.match_mask[i] {
-        write!(self.term, "{}", self.standout.apply_to(ch))?;
-      } else {
-        write!(self.term, "{}", ch)?;
+    let found = index.get(result.candidate_index).unwrap();
+    let width = self.max_display_width - spent_width;
+    // Never cut a cluster in half when matching grapheme clusters
+    let shown = if self.graphemes {
+      found.grapheme_indices(true).nth(width).map(|(i, _)| i)
+    } else {
+      found.char_indices().nth(width).map(|(i, _)| i)
+    }.unwrap_or(found.len());
+
+    let mut written = 0;
+    for range in result.match_ranges(found) {
+      if range.start >= shown {
+        break;
       }
+      let end = range.end.min(shown);
+      write!(self.term, "{}", &found[written..range.start])?;
+      write!(self.term, "{}", self.standout.apply_to(&found[range.start..end]))?;
+      written = end;
     }
+    write!(self.term, "{}", &found[written..shown])?;
 
     Ok(())
   }
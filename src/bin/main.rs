@@ -3,11 +3,11 @@ extern crate fzyr;
 mod interactive;
 mod opts;
 
-use std::io;
+use std::io::{self, Write};
 use std::process;
 
 use fzyr::config::SCORE_MIN;
-use fzyr::search_score;
+use fzyr::{explain, search_score, search_score_top, Query};
 
 fn candidates_from_stdin() -> Vec<String> {
   let stdin = io::stdin();
@@ -25,12 +25,18 @@ fn candidates_from_stdin() -> Vec<String> {
   out
 }
 
-fn to_slices<'src>(strings: &'src Vec<String>) -> Vec<&'src str> {
-  strings
-    .iter()
-    .map(|s| s.trim())
-    .filter(|s| !s.is_empty())
-    .collect()
+// The candidate on a line read from stdin, if there is one
+fn candidate(line: &str) -> Option<&str> {
+  let candidate = line.trim();
+  if candidate.is_empty() {
+    None
+  } else {
+    Some(candidate)
+  }
+}
+
+fn to_slices(strings: &[String]) -> Vec<&str> {
+  strings.iter().filter_map(|s| candidate(s)).collect()
 }
 
 fn run() -> i32 {
@@ -41,35 +47,97 @@ fn run() -> i32 {
     return 1;
   }
 
+  if options.query.is_empty() {
+    // Run interactively, searching the candidates while they're read
+    return interactive::run(&options);
+  }
+
   let candidates = candidates_from_stdin();
   let candidates = to_slices(&candidates);
 
+  let query = Query::with_options(&options.query, &options.query_options);
+
   if options.benchmark > 0 {
     // Run a benchmarking run without output
     for _ in 0..options.benchmark {
-      search_score(&options.query, &candidates, options.parallelism);
+      search_score(&query, &candidates, options.parallelism, &options.scoring);
     }
     0
-  } else if !options.query.is_empty() {
+  } else {
     // Run printing to stdout
-    let results = search_score(&options.query, &candidates, options.parallelism);
-    for result in results.iter().take(options.lines) {
-      if options.show_scores {
-        if result.score == SCORE_MIN {
-          print!("(     ) ");
-        } else {
-          print!("({:5.2}) ", result.score);
-        }
-        println!("{}", candidates[result.candidate_index]);
+    let stdout = io::stdout();
+    match print_results(&mut stdout.lock(), &query, &candidates, &options) {
+      Ok(()) => 0,
+      Err(_) => 1,
+    }
+  }
+}
+
+// Prints the best matches of the query, each after its score if those are
+// shown and followed by its explanation if asked for
+fn print_results<W: Write>(
+  out: &mut W,
+  query: &Query,
+  candidates: &[&str],
+  options: &opts::Options,
+) -> io::Result<()> {
+  let results = search_score_top(
+    query,
+    candidates,
+    options.lines,
+    options.parallelism,
+    &options.scoring,
+  );
+  for result in &results {
+    if options.show_scores {
+      if result.score == SCORE_MIN {
+        write!(out, "(     ) ")?;
+      } else {
+        write!(out, "({:5.2}) ", result.score)?;
       }
     }
-    0
-  } else {
-    // Run interactively
-    interactive::run(&candidates, &options)
+    let candidate = candidates[result.candidate_index];
+    writeln!(out, "{}", candidate)?;
+    if options.explain {
+      write!(out, "{}", explain(query, candidate, &options.scoring))?;
+    }
   }
+  Ok(())
 }
 
 fn main() {
   process::exit(run());
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn printed(options: &opts::Options) -> String {
+    let query = Query::new("amor");
+    let candidates = ["readme.md", "app/models/order", "amor"];
+    let mut out = Vec::new();
+    print_results(&mut out, &query, &candidates, options).unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn prints_candidates() {
+    // Scores are left out unless asked for
+    let options = opts::Options::default();
+    assert_eq!("amor\napp/models/order\n", printed(&options));
+  }
+
+  #[test]
+  fn prints_scores() {
+    let options = opts::Options {
+      show_scores: true,
+      ..opts::Options::default()
+    };
+    let printed = printed(&options);
+    let lines: Vec<&str> = printed.lines().collect();
+    assert_eq!(2, lines.len());
+    assert!(lines[0].starts_with('(') && lines[0].ends_with(") amor"));
+    assert!(lines[1].starts_with('(') && lines[1].ends_with(") app/models/order"));
+  }
+}
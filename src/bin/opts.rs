@@ -1,11 +1,18 @@
 extern crate clap;
 
-use self::clap::{App, Arg};
+use self::clap::{App, Arg, ArgMatches};
 
-pub const NAME: &'static str = env!("CARGO_PKG_NAME");
-pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
-pub const WEBSITE: &'static str = env!("CARGO_PKG_HOMEPAGE");
-pub const DESCRIPTION: &'static str = env!("CARGO_PKG_DESCRIPTION");
+use std::sync::Arc;
+
+use fzyr::{
+  BonusScheme, CaseMatching, DefaultBonuses, Normalization, PathScoring, QueryOptions,
+  RustPathBonuses, ScoringConfig, TieBreak, UrlBonuses, WindowsPathBonuses,
+};
+
+pub const NAME: &str = env!("CARGO_PKG_NAME");
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const WEBSITE: &str = env!("CARGO_PKG_HOMEPAGE");
+pub const DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
 
 pub const DEFLT_STRING_BUFFER_LEN: usize = 128;
 
@@ -17,6 +24,9 @@ pub struct Options {
   pub parallelism: usize,
   pub prompt: String,
   pub benchmark: usize,
+  pub explain: bool,
+  pub query_options: QueryOptions,
+  pub scoring: ScoringConfig,
 }
 
 impl Default for Options {
@@ -28,6 +38,9 @@ impl Default for Options {
       parallelism: 4,
       prompt: "> ".to_string(),
       benchmark: 0,
+      explain: false,
+      query_options: QueryOptions::default(),
+      scoring: ScoringConfig::default(),
     }
   }
 }
@@ -40,6 +53,28 @@ pub fn cmd_parse() -> Options {
   let deflt_parallelism = out.parallelism.to_string();
   let deflt_prompt = out.prompt.to_string();
   let deflt_benchmark = out.benchmark.to_string();
+  let deflt_case = case_name(out.query_options.case);
+  let deflt_normalize = normalization_name(out.query_options.normalization);
+  let deflt_gap_leading = out.scoring.gap_leading.to_string();
+  let deflt_gap_inner = out.scoring.gap_inner.to_string();
+  let deflt_gap_trailing = out.scoring.gap_trailing.to_string();
+  let deflt_match_consecutive = out.scoring.match_consecutive.to_string();
+  let deflt_match_slash = out.scoring.match_slash.to_string();
+  let deflt_match_word = out.scoring.match_word.to_string();
+  let deflt_match_capital = out.scoring.match_capital.to_string();
+  let deflt_match_dot = out.scoring.match_dot.to_string();
+  let deflt_bonuses = out.scoring.bonus_scheme.name().to_string();
+  let deflt_max_window = out.scoring.max_window.to_string();
+  let deflt_tie_breaks: Vec<&str> = out
+    .scoring
+    .tie_breaks
+    .iter()
+    .map(|&tie_break| tie_break_name(tie_break))
+    .collect();
+  let deflt_tie_breaks = deflt_tie_breaks.join(",");
+  let deflt_path = PathScoring::default();
+  let deflt_match_basename = deflt_path.match_basename.to_string();
+  let deflt_gap_directory = deflt_path.gap_directory.to_string();
 
   let long_about: String = format!("{}\n[{}]", DESCRIPTION, WEBSITE);
 
@@ -93,6 +128,11 @@ pub fn cmd_parse() -> Options {
         .default_value(&deflt_benchmark)
         .help("Set to a positive value to run that many repeated searches for benchmarking"),
     )
+    .arg(
+      Arg::with_name("explain")
+        .long("explain")
+        .help("Show how the score of each result is reached"),
+    )
     .arg(
       Arg::with_name("workers")
         .long("workers")
@@ -106,6 +146,137 @@ pub fn cmd_parse() -> Options {
         .value_name("QUERY")
         .help("Identical to \"--query\""),
     )
+    .arg(
+      Arg::with_name("case")
+        .long("case")
+        .value_name("MODE")
+        .possible_values(&["insensitive", "sensitive", "smart"])
+        .default_value(deflt_case)
+        .help("Case sensitivity, \"smart\" is sensitive only if the query has an uppercase letter"),
+    )
+    .arg(
+      Arg::with_name("extended")
+        .short("x")
+        .long("extended")
+        .help("Use the extended search syntax (e.g. \"^prefix 'exact suffix$ !not a | b\")"),
+    )
+    .arg(
+      Arg::with_name("normalize")
+        .long("normalize")
+        .value_name("FORM")
+        .possible_values(&["none", "nfc", "nfkc"])
+        .default_value(deflt_normalize)
+        .help("Unicode normalization applied to the query and candidates before matching"),
+    )
+    .arg(
+      Arg::with_name("strip-diacritics")
+        .long("strip-diacritics")
+        .help("Ignore diacritics when matching (e.g. \"e\" matches \"é\")"),
+    )
+    .arg(
+      Arg::with_name("graphemes")
+        .long("graphemes")
+        .help("Match and highlight whole grapheme clusters (e.g. emoji or accented letters)"),
+    )
+    .arg(
+      Arg::with_name("gap-leading")
+        .long("gap-leading")
+        .value_name("SCORE")
+        .default_value(&deflt_gap_leading)
+        .help("Score for each character before the first match"),
+    )
+    .arg(
+      Arg::with_name("gap-inner")
+        .long("gap-inner")
+        .value_name("SCORE")
+        .default_value(&deflt_gap_inner)
+        .help("Score for each character between matches"),
+    )
+    .arg(
+      Arg::with_name("gap-trailing")
+        .long("gap-trailing")
+        .value_name("SCORE")
+        .default_value(&deflt_gap_trailing)
+        .help("Score for each character after the last match"),
+    )
+    .arg(
+      Arg::with_name("match-consecutive")
+        .long("match-consecutive")
+        .value_name("SCORE")
+        .default_value(&deflt_match_consecutive)
+        .help("Bonus for a match directly following another match"),
+    )
+    .arg(
+      Arg::with_name("match-slash")
+        .long("match-slash")
+        .value_name("SCORE")
+        .default_value(&deflt_match_slash)
+        .help("Bonus for a match directly following a slash"),
+    )
+    .arg(
+      Arg::with_name("match-word")
+        .long("match-word")
+        .value_name("SCORE")
+        .default_value(&deflt_match_word)
+        .help("Bonus for a match at the start of a word"),
+    )
+    .arg(
+      Arg::with_name("match-capital")
+        .long("match-capital")
+        .value_name("SCORE")
+        .default_value(&deflt_match_capital)
+        .help("Bonus for a match on a capital following a lowercase letter"),
+    )
+    .arg(
+      Arg::with_name("match-dot")
+        .long("match-dot")
+        .value_name("SCORE")
+        .default_value(&deflt_match_dot)
+        .help("Bonus for a match directly following a dot"),
+    )
+    .arg(
+      Arg::with_name("bonuses")
+        .long("bonuses")
+        .value_name("SCHEME")
+        .possible_values(&["default", "windows", "rust", "url"])
+        .default_value(&deflt_bonuses)
+        .help("Where matches get bonuses, \"windows\" also after \\, \"rust\" after ::, \"url\" after URL punctuation"),
+    )
+    .arg(
+      Arg::with_name("max-window")
+        .long("max-window")
+        .value_name("CHARS")
+        .default_value(&deflt_max_window)
+        .help("Longer candidates are only scored in a window this long around their tightest match"),
+    )
+    .arg(
+      Arg::with_name("path")
+        .long("path")
+        .help("Score candidates as paths, preferring matches in the file name"),
+    )
+    .arg(
+      Arg::with_name("match-basename")
+        .long("match-basename")
+        .value_name("SCORE")
+        .default_value(&deflt_match_basename)
+        .help("With --path, bonus for a match in the last component of the path"),
+    )
+    .arg(
+      Arg::with_name("gap-directory")
+        .long("gap-directory")
+        .value_name("SCORE")
+        .default_value(&deflt_gap_directory)
+        .help("With --path, penalty for each directory separator between two matches"),
+    )
+    .arg(
+      Arg::with_name("tie-breaks")
+        .long("tie-breaks")
+        .value_name("ORDER")
+        .use_delimiter(true)
+        .possible_values(&["length", "index", "span"])
+        .default_value(&deflt_tie_breaks)
+        .help("How results with equal scores are ordered, by shorter candidate, earlier candidate or shorter match"),
+    )
     .get_matches();
 
   out.query = if matches.is_present("query") {
@@ -140,6 +311,102 @@ pub fn cmd_parse() -> Options {
     .unwrap_or(&deflt_benchmark)
     .parse()
     .unwrap_or(out.benchmark);
+  out.explain = matches.is_present("explain");
+  out.query_options.case = match matches.value_of("case") {
+    Some("sensitive") => CaseMatching::Sensitive,
+    Some("smart") => CaseMatching::Smart,
+    Some("insensitive") => CaseMatching::Insensitive,
+    _ => out.query_options.case,
+  };
+  out.query_options.extended = matches.is_present("extended");
+  out.query_options.normalization = match matches.value_of("normalize") {
+    Some("nfc") => Normalization::Nfc,
+    Some("nfkc") => Normalization::Nfkc,
+    Some("none") => Normalization::None,
+    _ => out.query_options.normalization,
+  };
+  out.query_options.strip_diacritics = matches.is_present("strip-diacritics");
+  out.query_options.graphemes = matches.is_present("graphemes");
+  out.scoring = ScoringConfig {
+    gap_leading: parse_score(&matches, "gap-leading", out.scoring.gap_leading),
+    gap_inner: parse_score(&matches, "gap-inner", out.scoring.gap_inner),
+    gap_trailing: parse_score(&matches, "gap-trailing", out.scoring.gap_trailing),
+    match_consecutive: parse_score(&matches, "match-consecutive", out.scoring.match_consecutive),
+    match_slash: parse_score(&matches, "match-slash", out.scoring.match_slash),
+    match_word: parse_score(&matches, "match-word", out.scoring.match_word),
+    match_capital: parse_score(&matches, "match-capital", out.scoring.match_capital),
+    match_dot: parse_score(&matches, "match-dot", out.scoring.match_dot),
+    bonus_scheme: matches
+      .value_of("bonuses")
+      .and_then(bonus_scheme)
+      .unwrap_or_else(|| out.scoring.bonus_scheme.clone()),
+    max_window: matches
+      .value_of("max-window")
+      .and_then(|value| value.parse().ok())
+      .unwrap_or(out.scoring.max_window),
+    path: if matches.is_present("path") {
+      Some(PathScoring {
+        match_basename: parse_score(&matches, "match-basename", deflt_path.match_basename),
+        gap_directory: parse_score(&matches, "gap-directory", deflt_path.gap_directory),
+      })
+    } else {
+      out.scoring.path
+    },
+    tie_breaks: match matches.values_of("tie-breaks") {
+      Some(values) => values.filter_map(tie_break).collect(),
+      None => out.scoring.tie_breaks.clone(),
+    },
+  };
 
   out
 }
+
+fn case_name(case: CaseMatching) -> &'static str {
+  match case {
+    CaseMatching::Insensitive => "insensitive",
+    CaseMatching::Sensitive => "sensitive",
+    CaseMatching::Smart => "smart",
+  }
+}
+
+fn normalization_name(normalization: Normalization) -> &'static str {
+  match normalization {
+    Normalization::None => "none",
+    Normalization::Nfc => "nfc",
+    Normalization::Nfkc => "nfkc",
+  }
+}
+
+fn tie_break_name(tie_break: TieBreak) -> &'static str {
+  match tie_break {
+    TieBreak::Length => "length",
+    TieBreak::Index => "index",
+    TieBreak::Span => "span",
+  }
+}
+
+fn tie_break(name: &str) -> Option<TieBreak> {
+  match name {
+    "length" => Some(TieBreak::Length),
+    "index" => Some(TieBreak::Index),
+    "span" => Some(TieBreak::Span),
+    _ => None,
+  }
+}
+
+fn bonus_scheme(name: &str) -> Option<Arc<dyn BonusScheme>> {
+  match name {
+    "default" => Some(Arc::new(DefaultBonuses)),
+    "windows" => Some(Arc::new(WindowsPathBonuses)),
+    "rust" => Some(Arc::new(RustPathBonuses)),
+    "url" => Some(Arc::new(UrlBonuses)),
+    _ => None,
+  }
+}
+
+fn parse_score(matches: &ArgMatches, name: &str, deflt: f64) -> f64 {
+  matches
+    .value_of(name)
+    .and_then(|value| value.parse().ok())
+    .unwrap_or(deflt)
+}
@@ -1,5 +1,21 @@
 mod score;
 mod search;
 
-pub use score::{config, has_match, locate, score, LocateResult, Score, ScoreResult};
-pub use search::{search_locate, search_score, LocateResults, ScoreResults};
+pub use score::config::{PathScoring, ScoringConfig, TieBreak};
+pub use score::{
+  config, explain, has_match, locate, score, Bonus, BonusScheme, CaseMatching, DefaultBonuses,
+  Explanation, Gap, LocateResult, Matcher, Normalization, Query, QueryOptions, RustPathBonuses,
+  Score, ScorePart, ScoreResult, TermExplanation, UrlBonuses, WindowsPathBonuses,
+};
+pub use search::{
+  search_fields, search_locate, search_locate_by, search_locate_cancellable, search_locate_iter,
+  search_locate_progress, search_locate_top, search_score, search_score_by,
+  search_score_cancellable, search_score_iter, search_score_progress, search_score_top,
+  CancellationToken, Cancelled, Feed, Field, FieldKey, FieldResult, FieldScoring, Index, ItemResult,
+  LocateResults, Progress, ScoreResults, Session,
+};
+#[cfg(feature = "async")]
+pub use search::{search_locate_stream, search_score_stream, Panicked, SearchStream};
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub use search::search_score_equal_split;
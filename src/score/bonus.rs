@@ -0,0 +1,266 @@
+use std::fmt;
+
+use super::config::ScoringConfig;
+use super::Score;
+
+/// The kind of bonus given for a match at a position in a candidate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bonus {
+  None,
+  /// Directly following another match
+  Consecutive,
+  /// Directly following a slash (or another path separator)
+  Slash,
+  /// At the start of a word
+  Word,
+  /// A capital following a lowercase letter
+  Capital,
+  /// Directly following a dot
+  Dot,
+}
+
+impl Bonus {
+  /// The score of this bonus under `config`
+  pub fn score(self, config: &ScoringConfig) -> Score {
+    match self {
+      Bonus::None => 0.0,
+      Bonus::Consecutive => config.match_consecutive,
+      Bonus::Slash => config.match_slash,
+      Bonus::Word => config.match_word,
+      Bonus::Capital => config.match_capital,
+      Bonus::Dot => config.match_dot,
+    }
+  }
+
+  pub(crate) fn name(self) -> &'static str {
+    match self {
+      Bonus::None => "no bonus",
+      Bonus::Consecutive => "consecutive",
+      Bonus::Slash => "slash",
+      Bonus::Word => "word",
+      Bonus::Capital => "capital",
+      Bonus::Dot => "dot",
+    }
+  }
+}
+
+/// Decides which bonus a match gets at each position of a candidate
+///
+/// The bonuses are weighted by the `ScoringConfig`. `Bonus::Consecutive` is
+/// given while scoring, so schemes should never give it themselves.
+pub trait BonusScheme: fmt::Debug + Send + Sync {
+  /// A short name for the scheme, schemes with the same name are considered
+  /// equal
+  fn name(&self) -> &str;
+
+  /// Replaces the contents of `out` with the bonus for a match at each
+  /// character of `candidate`
+  fn bonuses(&self, candidate: &[char], out: &mut Vec<Bonus>);
+
+  /// As `bonuses`, for a candidate that is all ASCII
+  ///
+  /// Override this to avoid converting ASCII candidates to characters.
+  fn ascii_bonuses(&self, candidate: &[u8], out: &mut Vec<Bonus>) {
+    let chars: Vec<char> = candidate.iter().map(|&b| b as char).collect();
+    self.bonuses(&chars, out);
+  }
+}
+
+/// Bonuses for Unix paths and general text: after `/`, after `.`, at the start
+/// of words separated by spaces, `-` or `_`, and at camelCase capitals
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultBonuses;
+
+/// As `DefaultBonuses`, also treating `\` as a path separator
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WindowsPathBonuses;
+
+/// As `DefaultBonuses`, also treating `::` as a path separator and the start
+/// of generic or function arguments as the start of a word
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RustPathBonuses;
+
+/// As `DefaultBonuses`, also treating the punctuation of URLs (such as `:`,
+/// `?`, `&`, `=` and `#`) as separating words
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UrlBonuses;
+
+impl BonusScheme for DefaultBonuses {
+  fn name(&self) -> &str {
+    "default"
+  }
+
+  fn bonuses(&self, candidate: &[char], out: &mut Vec<Bonus>) {
+    bonuses_by(candidate, out, default_bonus);
+  }
+
+  fn ascii_bonuses(&self, candidate: &[u8], out: &mut Vec<Bonus>) {
+    bonuses_by(candidate, out, default_bonus);
+  }
+}
+
+impl BonusScheme for WindowsPathBonuses {
+  fn name(&self) -> &str {
+    "windows"
+  }
+
+  fn bonuses(&self, candidate: &[char], out: &mut Vec<Bonus>) {
+    bonuses_by(candidate, out, windows_path_bonus);
+  }
+
+  fn ascii_bonuses(&self, candidate: &[u8], out: &mut Vec<Bonus>) {
+    bonuses_by(candidate, out, windows_path_bonus);
+  }
+}
+
+impl BonusScheme for RustPathBonuses {
+  fn name(&self) -> &str {
+    "rust"
+  }
+
+  fn bonuses(&self, candidate: &[char], out: &mut Vec<Bonus>) {
+    bonuses_by(candidate, out, rust_path_bonus);
+  }
+
+  fn ascii_bonuses(&self, candidate: &[u8], out: &mut Vec<Bonus>) {
+    bonuses_by(candidate, out, rust_path_bonus);
+  }
+}
+
+impl BonusScheme for UrlBonuses {
+  fn name(&self) -> &str {
+    "url"
+  }
+
+  fn bonuses(&self, candidate: &[char], out: &mut Vec<Bonus>) {
+    bonuses_by(candidate, out, url_bonus);
+  }
+
+  fn ascii_bonuses(&self, candidate: &[u8], out: &mut Vec<Bonus>) {
+    bonuses_by(candidate, out, url_bonus);
+  }
+}
+
+// Fills `out` with `rule(before, previous, current)` for each character, as
+// though the candidate followed a slash
+fn bonuses_by<C, F>(candidate: &[C], out: &mut Vec<Bonus>, rule: F)
+where
+  C: Copy + Into<char>,
+  F: Fn(char, char, char) -> Bonus,
+{
+  let mut before = '/';
+  let mut previous = '/';
+  out.clear();
+  out.extend(candidate.iter().map(|&current| {
+    let current = current.into();
+    let bonus = rule(before, previous, current);
+    before = previous;
+    previous = current;
+    bonus
+  }));
+}
+
+fn default_bonus(_: char, previous: char, current: char) -> Bonus {
+  if current.is_uppercase() && previous.is_lowercase() {
+    Bonus::Capital
+  } else {
+    match previous {
+      '/' => Bonus::Slash,
+      '.' => Bonus::Dot,
+      ' ' | '-' | '_' => Bonus::Word,
+      _ => Bonus::None,
+    }
+  }
+}
+
+fn windows_path_bonus(before: char, previous: char, current: char) -> Bonus {
+  match previous {
+    '\\' => Bonus::Slash,
+    _ => default_bonus(before, previous, current),
+  }
+}
+
+fn rust_path_bonus(before: char, previous: char, current: char) -> Bonus {
+  match (before, previous) {
+    (':', ':') => Bonus::Slash,
+    (_, '<') | (_, '(') | (_, ',') | (_, '&') => Bonus::Word,
+    _ => default_bonus(before, previous, current),
+  }
+}
+
+fn url_bonus(before: char, previous: char, current: char) -> Bonus {
+  match previous {
+    ':' | '?' | '&' | '=' | '#' | '@' | '+' => Bonus::Word,
+    _ => default_bonus(before, previous, current),
+  }
+}
+
+/// Weighs the bonus kinds of a candidate by `config`
+pub(crate) fn match_bonuses(bonuses: &[Bonus], config: &ScoringConfig, out: &mut Vec<Score>) {
+  out.clear();
+  out.extend(bonuses.iter().map(|bonus| bonus.score(config)));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn bonuses(scheme: &dyn BonusScheme, candidate: &str) -> Vec<Bonus> {
+    let chars: Vec<char> = candidate.chars().collect();
+    let mut out = Vec::new();
+    scheme.bonuses(&chars, &mut out);
+
+    if candidate.is_ascii() {
+      let mut ascii = Vec::new();
+      scheme.ascii_bonuses(candidate.as_bytes(), &mut ascii);
+      assert_eq!(out, ascii);
+    }
+    out
+  }
+
+  // Positions given each kind of bonus (other than none)
+  fn positions(bonuses: &[Bonus], kind: Bonus) -> Vec<usize> {
+    (0..bonuses.len()).filter(|&i| bonuses[i] == kind).collect()
+  }
+
+  #[test]
+  fn default_scheme() {
+    let out = bonuses(&DefaultBonuses, "src/fooBar.rs-x_y z");
+    assert_eq!(vec![0, 4], positions(&out, Bonus::Slash));
+    assert_eq!(vec![11], positions(&out, Bonus::Dot));
+    assert_eq!(vec![14, 16, 18], positions(&out, Bonus::Word));
+    assert_eq!(vec![7], positions(&out, Bonus::Capital));
+    assert!(!out.contains(&Bonus::Consecutive));
+
+    let out = bonuses(&DefaultBonuses, r"src\foo::bar");
+    assert_eq!(vec![0], positions(&out, Bonus::Slash));
+  }
+
+  #[test]
+  fn windows_scheme() {
+    let out = bonuses(&WindowsPathBonuses, r"C:\Users\me/foo.txt");
+    assert_eq!(vec![0, 3, 9, 12], positions(&out, Bonus::Slash));
+    assert_eq!(vec![16], positions(&out, Bonus::Dot));
+  }
+
+  #[test]
+  fn rust_scheme() {
+    let out = bonuses(&RustPathBonuses, "std::collections::HashMap<K,V>");
+    assert_eq!(vec![0, 5, 18], positions(&out, Bonus::Slash));
+    assert_eq!(vec![26, 28], positions(&out, Bonus::Word));
+    assert_eq!(vec![22], positions(&out, Bonus::Capital));
+
+    // A single colon isn't a separator
+    let out = bonuses(&RustPathBonuses, "a:b");
+    assert_eq!(vec![0], positions(&out, Bonus::Slash));
+    assert_eq!(Bonus::None, out[2]);
+  }
+
+  #[test]
+  fn url_scheme() {
+    let out = bonuses(&UrlBonuses, "https://example.com/search?q=rust#top");
+    assert_eq!(vec![0, 7, 8, 20], positions(&out, Bonus::Slash));
+    assert_eq!(vec![16], positions(&out, Bonus::Dot));
+    assert_eq!(vec![6, 27, 29, 34], positions(&out, Bonus::Word));
+  }
+}
@@ -1,7 +1,9 @@
 extern crate std;
 
 use std::f64;
+use std::sync::Arc;
 
+use score::bonus::{BonusScheme, DefaultBonuses};
 use score::Score;
 
 pub const SCORE_MIN: Score = f64::NEG_INFINITY;
@@ -17,8 +19,123 @@ pub const SCORE_MATCH_WORD: Score = 0.8;
 pub const SCORE_MATCH_CAPITAL: Score = 0.7;
 pub const SCORE_MATCH_DOT: Score = 0.6;
 
-pub const CANDIDATE_MAX_BYTES: usize = 2048;
-pub const CANDIDATE_MAX_CHARS: usize = 1024;
+pub const SCORE_MATCH_BASENAME: Score = 0.4;
+pub const SCORE_GAP_DIRECTORY: Score = -0.3;
+
+/// Default for `ScoringConfig::max_window`
+pub const WINDOW_MAX_CHARS: usize = 1024;
+
+/// Weights used when scoring a query against a candidate
+///
+/// The `Default` is made from the `SCORE_*` constants above.
+#[derive(Clone, Debug)]
+pub struct ScoringConfig {
+  pub gap_leading: Score,
+  pub gap_inner: Score,
+  pub gap_trailing: Score,
+
+  pub match_consecutive: Score,
+  pub match_slash: Score,
+  pub match_word: Score,
+  pub match_capital: Score,
+  pub match_dot: Score,
+
+  /// Which of the bonuses above a match gets at each position
+  pub bonus_scheme: Arc<dyn BonusScheme>,
+
+  /// Extra weights for scoring file paths, off by default
+  pub path: Option<PathScoring>,
+
+  /// Most characters of a candidate to find the best fuzzy match in
+  ///
+  /// Scoring takes time (and, when locating, memory) proportional to the
+  /// length of the candidate, so longer candidates are only scored in a
+  /// window this long, centred on the best scoring of their tightest matches.
+  pub max_window: usize,
+
+  /// How results with equal scores are ordered
+  ///
+  /// Ties are broken by each of these in turn, and finally by
+  /// `candidate_index`, so the order of search results never depends on the
+  /// parallelism of the search.
+  pub tie_breaks: Vec<TieBreak>,
+}
+
+impl Default for ScoringConfig {
+  fn default() -> Self {
+    Self {
+      gap_leading: SCORE_GAP_LEADING,
+      gap_inner: SCORE_GAP_INNER,
+      gap_trailing: SCORE_GAP_TRAILING,
+
+      match_consecutive: SCORE_MATCH_CONSECUTIVE,
+      match_slash: SCORE_MATCH_SLASH,
+      match_word: SCORE_MATCH_WORD,
+      match_capital: SCORE_MATCH_CAPITAL,
+      match_dot: SCORE_MATCH_DOT,
+
+      bonus_scheme: Arc::new(DefaultBonuses),
+
+      path: None,
+
+      max_window: WINDOW_MAX_CHARS,
+
+      tie_breaks: vec![TieBreak::Length, TieBreak::Index],
+    }
+  }
+}
+
+impl PartialEq for ScoringConfig {
+  fn eq(&self, other: &Self) -> bool {
+    self.gap_leading == other.gap_leading
+      && self.gap_inner == other.gap_inner
+      && self.gap_trailing == other.gap_trailing
+      && self.match_consecutive == other.match_consecutive
+      && self.match_slash == other.match_slash
+      && self.match_word == other.match_word
+      && self.match_capital == other.match_capital
+      && self.match_dot == other.match_dot
+      && self.bonus_scheme.name() == other.bonus_scheme.name()
+      && self.path == other.path
+      && self.max_window == other.max_window
+      && self.tie_breaks == other.tie_breaks
+  }
+}
+
+/// A way of ordering results with equal scores
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TieBreak {
+  /// Shorter candidates first
+  Length,
+  /// Lower `candidate_index` first
+  Index,
+  /// Matches spanning fewer characters first
+  ///
+  /// Scoring locates the match when this is configured, so `ScoreResult`s
+  /// are ordered as `LocateResult`s are, at the cost of locating.
+  Span,
+}
+
+/// Weights for scoring candidates as file paths, separated by `/` or `\`
+///
+/// The `Default` is made from the `SCORE_*` constants above.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathScoring {
+  /// Added for every match in the last component of the path
+  pub match_basename: Score,
+  /// Added for every separator between two matches, so that matches spread
+  /// across many directories score less
+  pub gap_directory: Score,
+}
+
+impl Default for PathScoring {
+  fn default() -> Self {
+    Self {
+      match_basename: SCORE_MATCH_BASENAME,
+      gap_directory: SCORE_GAP_DIRECTORY,
+    }
+  }
+}
 
 #[cfg(test)]
 mod tests {
@@ -40,6 +157,7 @@ mod tests {
     assert_positive(SCORE_MATCH_WORD);
     assert_positive(SCORE_MATCH_CAPITAL);
     assert_positive(SCORE_MATCH_DOT);
+    assert_positive(SCORE_MATCH_BASENAME);
   }
 
   #[test]
@@ -48,11 +166,29 @@ mod tests {
     assert_negative(SCORE_GAP_LEADING);
     assert_negative(SCORE_GAP_INNER);
     assert_negative(SCORE_GAP_TRAILING);
+    assert_negative(SCORE_GAP_DIRECTORY);
   }
 
   #[test]
   fn non_zero() {
-    assert_ne!(0, CANDIDATE_MAX_BYTES);
-    assert_ne!(0, CANDIDATE_MAX_CHARS);
+    assert_ne!(0, WINDOW_MAX_CHARS);
+  }
+
+  #[test]
+  fn default_config() {
+    let config = ScoringConfig::default();
+    assert_eq!(SCORE_GAP_LEADING, config.gap_leading);
+    assert_eq!(SCORE_GAP_INNER, config.gap_inner);
+    assert_eq!(SCORE_GAP_TRAILING, config.gap_trailing);
+    assert_eq!(SCORE_MATCH_CONSECUTIVE, config.match_consecutive);
+    assert_eq!(SCORE_MATCH_SLASH, config.match_slash);
+    assert_eq!(SCORE_MATCH_WORD, config.match_word);
+    assert_eq!(SCORE_MATCH_CAPITAL, config.match_capital);
+    assert_eq!(SCORE_MATCH_DOT, config.match_dot);
+    assert_eq!("default", config.bonus_scheme.name());
+    assert_eq!(None, config.path);
+    assert_eq!(WINDOW_MAX_CHARS, config.max_window);
+    assert_eq!(vec![TieBreak::Length, TieBreak::Index], config.tie_breaks);
+    assert_eq!(config, ScoringConfig::default());
   }
 }
@@ -0,0 +1,353 @@
+use std::fmt;
+
+use super::config::*;
+use super::{Bonus, Score};
+
+/// Breakdown of how the score of a candidate was reached
+///
+/// The score of a candidate is the sum of the scores of the best matching
+/// term of each group of the query (a query without the extended syntax is a
+/// single term), and the score of each term is the sum of its parts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+  pub score: Score,
+  /// One entry per group of the query, empty if the candidate doesn't match
+  pub terms: Vec<TermExplanation>,
+}
+
+/// Breakdown of the score of a single term of a query
+#[derive(Clone, Debug, PartialEq)]
+pub struct TermExplanation {
+  /// The term, as folded for matching
+  pub term: String,
+  pub score: Score,
+  /// Character positions of the matches in the candidate
+  pub positions: Vec<usize>,
+  /// The bonuses and penalties that add up to `score`, in candidate order
+  ///
+  /// This is empty when the term is the whole candidate, which always scores
+  /// `SCORE_MAX`.
+  pub parts: Vec<ScorePart>,
+}
+
+/// Part of the score of a term
+///
+/// Positions are character positions in the candidate, while lengths count
+/// the units matched (characters, or grapheme clusters).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScorePart {
+  Match {
+    position: usize,
+    bonus: Bonus,
+    score: Score,
+  },
+  Gap {
+    gap: Gap,
+    start: usize,
+    len: usize,
+    score: Score,
+  },
+  /// Extra score for a match in the last component of a path
+  Basename { position: usize, score: Score },
+}
+
+/// Where in a candidate a gap is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gap {
+  /// Before the first match
+  Leading,
+  /// Between matches
+  Inner,
+  /// After the last match
+  Trailing,
+  /// Directory separators between matches of a path, where the length is the
+  /// number of separators and the start is the first of them
+  Directory,
+}
+
+impl TermExplanation {
+  // Breaks down the score of a term matched at `positions`, where `run` means
+  // the term only matches consecutively
+  pub(crate) fn new(
+    term: String,
+    score: Score,
+    positions: Vec<usize>,
+    c_len: usize,
+    bonuses: &[Bonus],
+    run: bool,
+    config: &ScoringConfig,
+  ) -> Self {
+    let mut parts = Vec::new();
+    if positions.len() < c_len {
+      let first = positions[0];
+      let last = positions[positions.len() - 1];
+
+      if first > 0 {
+        parts.push(gap(Gap::Leading, 0, first, config.gap_leading));
+      }
+      parts.push(ScorePart::Match {
+        position: first,
+        bonus: bonuses[first],
+        score: bonuses[first].score(config),
+      });
+      for pair in positions.windows(2) {
+        let (previous, j) = (pair[0], pair[1]);
+        let bonus = if j == previous + 1 {
+          // A consecutive match takes the better of the two bonuses, except in
+          // an exact run where it is always consecutive
+          if run || config.match_consecutive >= bonuses[j].score(config) {
+            Bonus::Consecutive
+          } else {
+            bonuses[j]
+          }
+        } else {
+          parts.push(gap(Gap::Inner, previous + 1, j - previous - 1, config.gap_inner));
+          bonuses[j]
+        };
+        parts.push(ScorePart::Match {
+          position: j,
+          bonus,
+          score: bonus.score(config),
+        });
+      }
+      if last + 1 < c_len {
+        parts.push(gap(Gap::Trailing, last + 1, c_len - last - 1, config.gap_trailing));
+      }
+    }
+
+    Self {
+      term,
+      score,
+      positions,
+      parts,
+    }
+  }
+
+  // Adds the parts of the score that come from scoring the candidate as a path
+  pub(crate) fn add_path_parts(&mut self, basename_bonuses: &[Score], directory_gaps: &[Score]) {
+    let mut parts = Vec::with_capacity(self.parts.len());
+    for &part in &self.parts {
+      parts.push(part);
+      match part {
+        ScorePart::Match { position, .. } if basename_bonuses[position] != 0.0 => {
+          parts.push(ScorePart::Basename {
+            position,
+            score: basename_bonuses[position],
+          });
+        }
+        ScorePart::Gap {
+          gap: Gap::Inner,
+          start,
+          len,
+          ..
+        } => {
+          let separators: Vec<usize> =
+            (start..start + len).filter(|&j| directory_gaps[j] != 0.0).collect();
+          if let Some(&first) = separators.first() {
+            parts.push(ScorePart::Gap {
+              gap: Gap::Directory,
+              start: first,
+              len: separators.len(),
+              score: separators.iter().map(|&j| directory_gaps[j]).sum(),
+            });
+          }
+        }
+        _ => {}
+      }
+    }
+    self.parts = parts;
+  }
+}
+
+fn gap(gap: Gap, start: usize, len: usize, per_unit: Score) -> ScorePart {
+  ScorePart::Gap {
+    gap,
+    start,
+    len,
+    score: len as f64 * per_unit,
+  }
+}
+
+impl fmt::Display for Explanation {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "score {}", format_score(self.score))?;
+    for term in &self.terms {
+      writeln!(f, "  term {:?}: {}", term.term, format_score(term.score))?;
+      if term.parts.is_empty() {
+        writeln!(f, "    whole candidate")?;
+      }
+      for part in &term.parts {
+        match *part {
+          ScorePart::Match {
+            position,
+            bonus,
+            score,
+          } => writeln!(f, "    match at {} ({}): {:+.3}", position, bonus.name(), score)?,
+          ScorePart::Gap {
+            gap,
+            start,
+            len,
+            score,
+          } => writeln!(
+            f,
+            "    {} gap of {} at {}: {:+.3}",
+            gap.name(),
+            len,
+            start,
+            score
+          )?,
+          ScorePart::Basename { position, score } => {
+            writeln!(f, "    basename at {}: {:+.3}", position, score)?
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+fn format_score(score: Score) -> String {
+  if score == SCORE_MIN {
+    "no match".to_string()
+  } else if score == SCORE_MAX {
+    "max".to_string()
+  } else {
+    format!("{:.3}", score)
+  }
+}
+
+impl Gap {
+  fn name(self) -> &'static str {
+    match self {
+      Gap::Leading => "leading",
+      Gap::Inner => "inner",
+      Gap::Trailing => "trailing",
+      Gap::Directory => "directory",
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use score::{explain, score, Query, QueryOptions};
+
+  fn parts_total(term: &TermExplanation) -> Score {
+    term
+      .parts
+      .iter()
+      .map(|part| match *part {
+        ScorePart::Match { score, .. }
+        | ScorePart::Gap { score, .. }
+        | ScorePart::Basename { score, .. } => score,
+      })
+      .sum()
+  }
+
+  #[test]
+  fn parts_add_up() {
+    let config = ScoringConfig::default();
+    let extended = QueryOptions {
+      extended: true,
+      ..QueryOptions::default()
+    };
+    let cases = [
+      (Query::new("amor"), "app/models/order"),
+      (Query::new("qart"), "QuArTz"),
+      (Query::new("gemfil"), "Gemfile.lock"),
+      (Query::new("аб"), "*аБ.аб"),
+      (Query::with_options("mat .rs$ | .toml$ !foo", &extended), "src/score/matcher.rs"),
+      (Query::with_options("'ore ^src", &extended), "src/score/matcher.rs"),
+    ];
+
+    for &(ref query, candidate) in &cases {
+      let explanation = explain(query, candidate, &config);
+      assert_eq!(score(query, candidate, &config).score, explanation.score);
+
+      let mut total = 0.0;
+      for term in &explanation.terms {
+        assert!((term.score - parts_total(term)).abs() < 1e-9, "{:?}", term);
+        total += term.score;
+      }
+      assert!((explanation.score - total).abs() < 1e-9);
+    }
+  }
+
+  #[test]
+  fn breakdown() {
+    let config = ScoringConfig::default();
+    let explanation = explain(&Query::new("amo"), "xapp/models", &config);
+    assert_eq!(1, explanation.terms.len());
+    let term = &explanation.terms[0];
+    assert_eq!("amo", term.term);
+    assert_eq!(vec![1, 5, 6], term.positions);
+    assert_eq!(
+      vec![
+        ScorePart::Gap {
+          gap: Gap::Leading,
+          start: 0,
+          len: 1,
+          score: SCORE_GAP_LEADING,
+        },
+        ScorePart::Match {
+          position: 1,
+          bonus: Bonus::None,
+          score: 0.0,
+        },
+        ScorePart::Gap {
+          gap: Gap::Inner,
+          start: 2,
+          len: 3,
+          score: SCORE_GAP_INNER * 3.0,
+        },
+        ScorePart::Match {
+          position: 5,
+          bonus: Bonus::Slash,
+          score: SCORE_MATCH_SLASH,
+        },
+        ScorePart::Match {
+          position: 6,
+          bonus: Bonus::Consecutive,
+          score: SCORE_MATCH_CONSECUTIVE,
+        },
+        ScorePart::Gap {
+          gap: Gap::Trailing,
+          start: 7,
+          len: 4,
+          score: SCORE_GAP_TRAILING * 4.0,
+        },
+      ],
+      term.parts
+    );
+  }
+
+  #[test]
+  fn special_scores() {
+    let config = ScoringConfig::default();
+    let explanation = explain(&Query::new("abc"), "ABC", &config);
+    assert_eq!(SCORE_MAX, explanation.score);
+    assert!(explanation.terms[0].parts.is_empty());
+
+    let explanation = explain(&Query::new("abc"), "cba", &config);
+    assert_eq!(SCORE_MIN, explanation.score);
+    assert!(explanation.terms.is_empty());
+
+    let explanation = explain(&Query::new(""), "abc", &config);
+    assert_eq!(SCORE_MIN, explanation.score);
+    assert!(explanation.to_string().contains("no match"));
+  }
+
+  #[test]
+  fn original_positions() {
+    let config = ScoringConfig::default();
+    let query = Query::with_options(
+      "b👍🏽c",
+      &QueryOptions {
+        graphemes: true,
+        ..QueryOptions::default()
+      },
+    );
+    let explanation = explain(&query, "a/b👍🏽c", &config);
+    assert_eq!(vec![2, 3, 5], explanation.terms[0].positions);
+  }
+}
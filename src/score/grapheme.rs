@@ -0,0 +1,127 @@
+extern crate unicode_segmentation;
+
+use self::unicode_segmentation::UnicodeSegmentation;
+
+use super::query::Query;
+
+/// Id of a grapheme cluster that doesn't appear in the query
+pub(crate) const OTHER_GRAPHEME: u32 = u32::MAX;
+
+/// The distinct (normalized and folded) grapheme clusters of a query
+///
+/// Clusters are matched as single units by comparing their ids, so that
+/// candidates never need to hold on to the text of their clusters.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct GraphemeTable {
+  clusters: Vec<String>,
+}
+
+impl GraphemeTable {
+  /// The id of a cluster, adding it to the table if it is new
+  pub(crate) fn intern(&mut self, cluster: &str) -> u32 {
+    match self.clusters.iter().position(|c| c == cluster) {
+      Some(id) => id as u32,
+      None => {
+        self.clusters.push(cluster.to_string());
+        self.clusters.len() as u32 - 1
+      }
+    }
+  }
+
+  /// The id of a cluster, or `OTHER_GRAPHEME` if it isn't in the table
+  pub(crate) fn id(&self, cluster: &str) -> u32 {
+    self
+      .clusters
+      .iter()
+      .position(|c| c == cluster)
+      .map_or(OTHER_GRAPHEME, |id| id as u32)
+  }
+}
+
+/// A candidate split into grapheme clusters
+#[derive(Debug, Default)]
+pub(crate) struct Segments {
+  /// Id of each cluster, in the query's `GraphemeTable`
+  pub(crate) ids: Vec<u32>,
+  /// First (normalized) character of each cluster, for the match bonuses
+  pub(crate) firsts: Vec<char>,
+  /// Index of each cluster among all the clusters of the candidate, which
+  /// differs from its position in `ids` once normalization has removed a
+  /// cluster entirely
+  pub(crate) clusters: Vec<usize>,
+  /// Index of the first original character of each cluster, followed by the
+  /// total number of original characters
+  pub(crate) cluster_starts: Vec<usize>,
+  normalized: Vec<char>,
+  folded: String,
+}
+
+impl Segments {
+  /// Splits `candidate` into the clusters of `query`
+  pub(crate) fn segment(&mut self, query: &Query, candidate: &str) {
+    self.ids.clear();
+    self.firsts.clear();
+    self.clusters.clear();
+    self.cluster_starts.clear();
+
+    let normalizer = query.normalizer();
+    let mut original_index = 0;
+    for (cluster, grapheme) in candidate.graphemes(true).enumerate() {
+      self.cluster_starts.push(original_index);
+      original_index += grapheme.chars().count();
+
+      self.normalized.clear();
+      normalizer.normalize(grapheme, &mut self.normalized);
+      let first = match self.normalized.first() {
+        Some(&first) => first,
+        None => continue,
+      };
+
+      self.folded.clear();
+      self.folded.extend(self.normalized.iter().map(|&c| query.fold(c)));
+      self.ids.push(query.grapheme_id(&self.folded));
+      self.firsts.push(first);
+      self.clusters.push(cluster);
+    }
+    self.cluster_starts.push(original_index);
+  }
+}
+
+/// Splits text into extended grapheme clusters
+pub(crate) fn graphemes(text: &str) -> impl Iterator<Item = &str> {
+  text.graphemes(true)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use score::QueryOptions;
+
+  #[test]
+  fn table() {
+    let mut table = GraphemeTable::default();
+    assert_eq!(0, table.intern("a"));
+    assert_eq!(1, table.intern("🇬🇧"));
+    assert_eq!(0, table.intern("a"));
+    assert_eq!(1, table.id("🇬🇧"));
+    assert_eq!(OTHER_GRAPHEME, table.id("🇫🇷"));
+  }
+
+  #[test]
+  fn segments() {
+    let query = Query::with_options(
+      "É👍🏽",
+      &QueryOptions {
+        graphemes: true,
+        ..QueryOptions::default()
+      },
+    );
+    let mut segments = Segments::default();
+    segments.segment(&query, "xÉ👍🏽👨‍👩‍👧e\u{301}");
+
+    assert_eq!(vec![OTHER_GRAPHEME, 0, 1, OTHER_GRAPHEME, OTHER_GRAPHEME], segments.ids);
+    assert_eq!(vec!['x', 'É', '👍', '👨', 'e'], segments.firsts);
+    assert_eq!(vec![0, 1, 2, 3, 4], segments.clusters);
+    assert_eq!(vec![0, 1, 2, 4, 9, 11], segments.cluster_starts);
+  }
+}
@@ -0,0 +1,1420 @@
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use super::bit_vec::BitVec;
+use super::config::*;
+use super::explain::TermExplanation;
+use super::grapheme::Segments;
+use super::normalize::Normalizer;
+use super::query::{fold_case, MatchUnit, Term, TermKind};
+use super::bonus::{match_bonuses, Bonus};
+use super::{
+  has_match_ascii, has_match_groups, path_weights, Explanation, LocateResult, Query, Score,
+  ScorePart, ScoreResult,
+};
+
+/// Scores candidates against a single query, reusing its buffers between
+/// candidates
+///
+/// Scoring with a `Matcher` only allocates when a candidate is longer than
+/// any previously seen, so it is the preferred way to score many candidates
+/// against the same query.
+#[derive(Debug)]
+pub struct Matcher {
+  query: Query,
+  config: ScoringConfig,
+  candidate: Candidate,
+  scratch: Scratch,
+  // Masks for choosing between alternative terms while locating
+  term_mask: BitVec,
+  best_term_mask: BitVec,
+  // Mask over the normalized characters of a candidate, before it is mapped
+  // back to the original characters
+  normalized_mask: BitVec,
+}
+
+// The candidate currently being scored
+#[derive(Debug, Default)]
+struct Candidate {
+  // Whether the candidate (and the query) is ASCII, in which case only
+  // `bytes` is filled
+  ascii: bool,
+  // Whether the candidate is matched by grapheme cluster, in which case only
+  // `segments` is filled
+  graphemes: bool,
+  // Whether `chars` were normalized, in which case they may not correspond
+  // one-to-one with the characters of the original candidate
+  normalized: bool,
+  chars: Vec<char>,
+  folded: Vec<char>,
+  bytes: Vec<u8>,
+  // Grapheme cluster of each normalized character
+  clusters: Vec<usize>,
+  // Index of the first original character of each cluster, and the total
+  cluster_starts: Vec<usize>,
+  segments: Segments,
+  bonuses: Vec<Bonus>,
+  match_bonuses: Vec<Score>,
+  // Whether the candidate is scored as a path, in which case the path weights
+  // are filled
+  path: bool,
+  basename_bonuses: Vec<Score>,
+  directory_gaps: Vec<Score>,
+}
+
+// The extra weights of a candidate scored as a path
+#[derive(Clone, Copy)]
+struct PathWeights<'a> {
+  basename_bonuses: &'a [Score],
+  directory_gaps: &'a [Score],
+}
+
+/// The parts of a candidate that don't depend on the query, worked out once
+/// for a `ScoringConfig` so that an `Index` can cache them
+#[derive(Clone, Debug)]
+pub(crate) struct PreparedCandidate {
+  ascii: bool,
+  // Case folded bytes of an ASCII candidate, or characters of any other
+  folded_bytes: Vec<u8>,
+  folded: Vec<char>,
+  match_bonuses: Vec<Score>,
+  basename_bonuses: Vec<Score>,
+  directory_gaps: Vec<Score>,
+  // Normalized characters of a non-ASCII candidate, worked out for the first
+  // normalizing query it's matched against
+  normalized: OnceLock<Normalized>,
+}
+
+#[derive(Clone, Debug)]
+struct Normalized {
+  normalizer: Normalizer,
+  chars: Vec<char>,
+  // The characters case folded
+  folded: Vec<char>,
+}
+
+#[derive(Debug, Default)]
+struct Scratch {
+  // Rows of the best score for each position ending in a match
+  best_score_w_ending: Vec<Score>,
+  // Rows of the best score for each position
+  best_score_overall: Vec<Score>,
+  // Rows of whether the best score for each position ending in a match comes
+  // from a consecutive match, for tracing the optimal path back
+  consecutive: Vec<bool>,
+  // Possible starting positions of an exact term
+  starts: Vec<usize>,
+  // Positions of the tightest match of each prefix of a fuzzy term, one row
+  // per prefix
+  prefix_paths: Vec<usize>,
+  // Positions of the best tight fuzzy match
+  path: Vec<usize>,
+}
+
+impl Matcher {
+  pub fn new(query: &Query, config: &ScoringConfig) -> Self {
+    Self {
+      query: query.clone(),
+      config: config.clone(),
+      candidate: Candidate::default(),
+      scratch: Scratch::default(),
+      term_mask: BitVec::new(),
+      best_term_mask: BitVec::new(),
+      normalized_mask: BitVec::new(),
+    }
+  }
+
+  /// Calculates a score for how well the query matches a `candidate`
+  ///
+  /// Higher scores are better
+  pub fn score(&mut self, candidate: &str) -> ScoreResult {
+    self.score_indexed(candidate, 0)
+  }
+
+  /// Calculates a score for how well the query matches a `candidate` and
+  /// gives the locations of the query characters in the `candidate` too
+  ///
+  /// Higher scores are better
+  pub fn locate(&mut self, candidate: &str) -> LocateResult {
+    self.locate_indexed(candidate, 0)
+  }
+
+  /// Explains how the score of `candidate` is reached
+  pub fn explain(&mut self, candidate: &str) -> Explanation {
+    let mut out = Explanation {
+      score: SCORE_MIN,
+      terms: Vec::new(),
+    };
+    if !self.prepare(candidate) {
+      return out;
+    }
+
+    let Matcher {
+      ref query,
+      ref config,
+      candidate: ref prepared,
+      ref mut scratch,
+      ref mut term_mask,
+      ref mut best_term_mask,
+      ..
+    } = *self;
+    let c_len = prepared.len();
+
+    let mut total = None;
+    for group in query.groups() {
+      // As in `score_groups`, but keeping the best term of each group
+      let mut best: Option<(Score, &Term)> = None;
+      for term in group {
+        if term.is_inverse() || !prepared.is_match(term) {
+          continue;
+        }
+        term_mask.truncate(0);
+        term_mask.grow(c_len, false);
+        let score = score_term(term, prepared, config, scratch, Some(term_mask));
+        if best.is_none_or(|(best, _)| score > best) {
+          best = Some((score, term));
+          std::mem::swap(term_mask, best_term_mask);
+        }
+      }
+
+      if let Some((score, term)) = best {
+        if score == SCORE_MIN {
+          out.terms.clear();
+          return out;
+        }
+        let positions = (0..c_len).filter(|&j| best_term_mask[j]).collect();
+        let mut explanation = TermExplanation::new(
+          term.folded().iter().collect(),
+          score,
+          positions,
+          c_len,
+          &prepared.bonuses,
+          term.kind() != TermKind::Fuzzy,
+          config,
+        );
+        if let Some(path) = prepared.path_weights() {
+          explanation.add_path_parts(path.basename_bonuses, path.directory_gaps);
+        }
+        out.terms.push(explanation);
+        total = Some(total.map_or(score, |total| total + score));
+      }
+    }
+    out.score = total.unwrap_or(SCORE_MIN);
+
+    if prepared.normalized || prepared.graphemes {
+      // Give positions in the original characters
+      let (clusters, cluster_starts) = prepared.clusters();
+      let original = |j: usize| cluster_starts[clusters[j]];
+      for term in &mut out.terms {
+        for position in &mut term.positions {
+          *position = original(*position);
+        }
+        for part in &mut term.parts {
+          match *part {
+            ScorePart::Match {
+              ref mut position,
+              ..
+            }
+            | ScorePart::Basename {
+              ref mut position,
+              ..
+            } => *position = original(*position),
+            ScorePart::Gap { ref mut start, .. } => *start = original(*start),
+          }
+        }
+      }
+    }
+
+    out
+  }
+
+  pub(crate) fn score_indexed(&mut self, candidate: &str, index: usize) -> ScoreResult {
+    if !self.prepare(candidate) {
+      return ScoreResult::with_score(index, SCORE_MIN);
+    }
+    self.score_prepared(index)
+  }
+
+  // As `score_indexed`, for a candidate already prepared for the config
+  pub(crate) fn score_cached(
+    &mut self,
+    candidate: &str,
+    cached: &PreparedCandidate,
+    index: usize,
+  ) -> ScoreResult {
+    if !self.prepare_cached(candidate, cached) {
+      return ScoreResult::with_score(index, SCORE_MIN);
+    }
+    self.score_prepared(index)
+  }
+
+  // As `has_match`, for a candidate already prepared for the config, matching
+  // its cached folded and normalized characters rather than working them out
+  // again
+  pub(crate) fn has_match_cached(&mut self, candidate: &str, cached: &PreparedCandidate) -> bool {
+    let Matcher {
+      ref query,
+      candidate: ref mut prepared,
+      ..
+    } = *self;
+    if query.is_ascii() && cached.ascii && !(query.matches_graphemes() && candidate.contains('\r')) {
+      if let Some(term) = query.fuzzy_term() {
+        return has_match_ascii(query, term, candidate.as_bytes());
+      }
+      if query.is_case_sensitive() {
+        return has_match_groups(query, candidate.as_bytes());
+      }
+      return has_match_groups(query, &cached.folded_bytes);
+    }
+
+    if query.matches_graphemes() {
+      // Clusters are matched by ids from the query, so they can't be cached
+      prepared.segments.segment(query, candidate);
+      return has_match_groups(query, &prepared.segments.ids);
+    }
+
+    let normalizer = query.normalizer();
+    let normalized = if normalizer.is_identity() || cached.ascii {
+      // Normalizing ASCII changes nothing
+      None
+    } else {
+      Some(cached.normalized.get_or_init(|| Normalized::new(normalizer, candidate)))
+    };
+    let folded = match normalized {
+      Some(normalized) if normalized.normalizer == normalizer => {
+        if query.is_case_sensitive() {
+          &normalized.chars
+        } else {
+          &normalized.folded
+        }
+      }
+      Some(_) => {
+        // Cached for another normalizer
+        prepared.folded.clear();
+        normalizer.normalize_graphemes(candidate, &mut prepared.folded);
+        for c in &mut prepared.folded {
+          *c = query.fold(*c);
+        }
+        &prepared.folded
+      }
+      None if !query.is_case_sensitive() && !cached.ascii => &cached.folded,
+      None => {
+        prepared.folded.clear();
+        prepared.folded.extend(candidate.chars().map(|c| query.fold(c)));
+        &prepared.folded
+      }
+    };
+    has_match_groups(query, folded)
+  }
+
+  pub(crate) fn locate_indexed(&mut self, candidate: &str, index: usize) -> LocateResult {
+    if !self.prepare(candidate) {
+      return LocateResult::with_score(index, candidate.chars().count(), SCORE_MIN);
+    }
+    self.locate_prepared(index)
+  }
+
+  // As `locate_indexed`, for a candidate already prepared for the config
+  pub(crate) fn locate_cached(
+    &mut self,
+    candidate: &str,
+    cached: &PreparedCandidate,
+    index: usize,
+  ) -> LocateResult {
+    if !self.prepare_cached(candidate, cached) {
+      return LocateResult::with_score(index, candidate.chars().count(), SCORE_MIN);
+    }
+    self.locate_prepared(index)
+  }
+
+  fn score_prepared(&mut self, index: usize) -> ScoreResult {
+    if self.config.tie_breaks.contains(&TieBreak::Span) {
+      return self.score_spanned(index);
+    }
+    let mut out = ScoreResult::with_score(index, self.score_groups(None));
+    // Results that don't score (as for an empty query) stay in candidate order
+    if out.score != SCORE_MIN {
+      out.break_ties(&self.config.tie_breaks, self.candidate.original_len(), None);
+    }
+    out
+  }
+
+  // As `score_prepared`, locating the match to break ties by its span as a
+  // `LocateResult` would
+  fn score_spanned(&mut self, index: usize) -> ScoreResult {
+    let mut mask = std::mem::take(&mut self.normalized_mask);
+    mask.truncate(0);
+    mask.grow(self.candidate.len(), false);
+    let mut out = ScoreResult::with_score(index, self.score_groups(Some(&mut mask)));
+    if out.score != SCORE_MIN {
+      let mut matched = (0..mask.len()).filter(|&j| mask[j]);
+      let span = matched.next().map(|first| {
+        let last = matched.next_back().unwrap_or(first);
+        self.candidate.original_span(first, last)
+      });
+      out.break_ties(&self.config.tie_breaks, self.candidate.original_len(), span);
+    }
+    self.normalized_mask = mask;
+    out
+  }
+
+  fn locate_prepared(&mut self, index: usize) -> LocateResult {
+    let mut out = self.locate_groups(index);
+    if out.score != SCORE_MIN {
+      out.break_ties(&self.config.tie_breaks);
+    }
+    out
+  }
+
+  // Locates the prepared candidate, marking the original characters matched
+  fn locate_groups(&mut self, index: usize) -> LocateResult {
+    if !self.candidate.normalized && !self.candidate.graphemes {
+      let mut out = LocateResult::new(index, self.candidate.len());
+      out.score = self.score_groups(Some(&mut out.match_mask));
+      return out;
+    }
+
+    let mut mask = std::mem::take(&mut self.normalized_mask);
+    mask.truncate(0);
+    mask.grow(self.candidate.len(), false);
+    let score = self.score_groups(Some(&mut mask));
+
+    // Highlight every original character of a cluster with a match in it
+    let (clusters, cluster_starts) = self.candidate.clusters();
+    let mut out = LocateResult::with_score(index, *cluster_starts.last().unwrap(), score);
+    for j in (0..mask.len()).filter(|&j| mask[j]) {
+      let cluster = clusters[j];
+      for i in cluster_starts[cluster]..cluster_starts[cluster + 1] {
+        out.match_mask.set(i, true);
+      }
+    }
+    self.normalized_mask = mask;
+    out
+  }
+
+  // Prepares the buffers for a new candidate, returns `false` if it can't be
+  // scored at all
+  fn prepare(&mut self, candidate: &str) -> bool {
+    if self.query.is_empty() {
+      return false;
+    }
+
+    let query = &self.query;
+    let scheme = &*self.config.bonus_scheme;
+    let prepared = &mut self.candidate;
+    prepared.ascii = query.is_ascii_match(candidate);
+    prepared.graphemes = !prepared.ascii && query.matches_graphemes();
+    if prepared.ascii {
+      prepared.normalized = false;
+      prepared.bytes.clear();
+      prepared.bytes.extend_from_slice(candidate.as_bytes());
+      scheme.ascii_bonuses(&prepared.bytes, &mut prepared.bonuses);
+      for byte in &mut prepared.bytes {
+        *byte = query.fold_byte(*byte);
+      }
+    } else if prepared.graphemes {
+      prepared.normalized = false;
+      prepared.segments.segment(query, candidate);
+      scheme.bonuses(&prepared.segments.firsts, &mut prepared.bonuses);
+    } else {
+      let normalizer = query.normalizer();
+      prepared.normalized = !normalizer.is_identity();
+      if prepared.normalized {
+        normalizer.normalize_candidate(
+          candidate,
+          &mut prepared.chars,
+          &mut prepared.clusters,
+          &mut prepared.cluster_starts,
+        );
+      } else {
+        prepared.chars.clear();
+        prepared.chars.extend(candidate.chars());
+      }
+      scheme.bonuses(&prepared.chars, &mut prepared.bonuses);
+      prepared.folded.clear();
+      prepared.folded.extend(prepared.chars.iter().map(|&c| query.fold(c)));
+    }
+    match_bonuses(&prepared.bonuses, &self.config, &mut prepared.match_bonuses);
+
+    prepared.path = self.config.path.is_some();
+    if let Some(ref path) = self.config.path {
+      let basename_bonuses = &mut prepared.basename_bonuses;
+      let directory_gaps = &mut prepared.directory_gaps;
+      if prepared.ascii {
+        path_weights(&prepared.bytes, path, basename_bonuses, directory_gaps);
+      } else if prepared.graphemes {
+        path_weights(&prepared.segments.firsts, path, basename_bonuses, directory_gaps);
+      } else {
+        path_weights(&prepared.chars, path, basename_bonuses, directory_gaps);
+      }
+    }
+
+    true
+  }
+
+  // As `prepare`, copying what it can from the cached parts of the candidate
+  fn prepare_cached(&mut self, candidate: &str, cached: &PreparedCandidate) -> bool {
+    let query = &self.query;
+    if query.matches_graphemes() || !query.normalizer().is_identity() {
+      // The units of the candidate depend on the query
+      return self.prepare(candidate);
+    }
+    if query.is_empty() {
+      return false;
+    }
+
+    let prepared = &mut self.candidate;
+    prepared.ascii = cached.ascii && query.is_ascii();
+    prepared.graphemes = false;
+    prepared.normalized = false;
+    if prepared.ascii {
+      prepared.bytes.clear();
+      if query.is_case_sensitive() {
+        prepared.bytes.extend_from_slice(candidate.as_bytes());
+      } else {
+        prepared.bytes.extend_from_slice(&cached.folded_bytes);
+      }
+    } else {
+      prepared.folded.clear();
+      if query.is_case_sensitive() {
+        prepared.folded.extend(candidate.chars());
+      } else if cached.ascii {
+        prepared.folded.extend(cached.folded_bytes.iter().map(|&b| b as char));
+      } else {
+        prepared.folded.extend_from_slice(&cached.folded);
+      }
+    }
+    prepared.match_bonuses.clone_from(&cached.match_bonuses);
+    prepared.path = self.config.path.is_some();
+    prepared.basename_bonuses.clone_from(&cached.basename_bonuses);
+    prepared.directory_gaps.clone_from(&cached.directory_gaps);
+
+    true
+  }
+
+  // Scores the prepared candidate against every group of the query, adding up
+  // the best score of each group
+  fn score_groups(&mut self, mut mask: Option<&mut BitVec>) -> Score {
+    let Matcher {
+      ref query,
+      ref config,
+      ref candidate,
+      ref mut scratch,
+      ref mut term_mask,
+      ref mut best_term_mask,
+      ..
+    } = *self;
+    let c_len = candidate.len();
+    let mut total = None;
+
+    for group in query.groups() {
+      let mut best = None;
+      for term in group {
+        if term.is_inverse() || !candidate.is_match(term) {
+          continue;
+        }
+
+        let score = if group.len() == 1 {
+          // No alternatives, so write straight to the output
+          score_term(term, candidate, config, scratch, mask.as_deref_mut())
+        } else if mask.is_some() {
+          term_mask.truncate(0);
+          term_mask.grow(c_len, false);
+          let score = score_term(term, candidate, config, scratch, Some(term_mask));
+          if best.is_none_or(|best| score > best) {
+            std::mem::swap(term_mask, best_term_mask);
+          }
+          score
+        } else {
+          score_term(term, candidate, config, scratch, None)
+        };
+        best = Some(best.map_or(score, |best: Score| best.max(score)));
+      }
+
+      if let Some(best) = best {
+        if best == SCORE_MIN {
+          return SCORE_MIN;
+        }
+        if group.len() > 1 {
+          if let Some(ref mut mask) = mask {
+            mask.union(best_term_mask);
+          }
+        }
+        total = Some(total.map_or(best, |total| total + best));
+      }
+    }
+
+    total.unwrap_or(SCORE_MIN)
+  }
+}
+
+impl Candidate {
+  fn len(&self) -> usize {
+    if self.ascii {
+      self.bytes.len()
+    } else if self.graphemes {
+      self.segments.ids.len()
+    } else {
+      self.folded.len()
+    }
+  }
+
+  // Number of characters in the original candidate
+  fn original_len(&self) -> usize {
+    if self.normalized || self.graphemes {
+      *self.clusters().1.last().unwrap()
+    } else {
+      self.len()
+    }
+  }
+
+  // How many original characters there are from the one at `first` to the
+  // one at `last`, including any others in their clusters
+  fn original_span(&self, first: usize, last: usize) -> usize {
+    if self.normalized || self.graphemes {
+      let (clusters, cluster_starts) = self.clusters();
+      cluster_starts[clusters[last] + 1] - cluster_starts[clusters[first]]
+    } else {
+      last - first + 1
+    }
+  }
+
+  fn is_match(&self, term: &Term) -> bool {
+    if self.ascii {
+      term.is_match(&self.bytes)
+    } else if self.graphemes {
+      term.is_match(&self.segments.ids)
+    } else {
+      term.is_match(&self.folded)
+    }
+  }
+
+  fn best_tight_path(&self, term: &Term, config: &ScoringConfig, scratch: &mut Scratch) {
+    let score = |path: &[usize]| score_path(path, self, config);
+    let (rows, path) = (&mut scratch.prefix_paths, &mut scratch.path);
+    if self.ascii {
+      best_tight_path(&self.bytes, term, config.max_window, &score, rows, path);
+    } else if self.graphemes {
+      best_tight_path(&self.segments.ids, term, config.max_window, &score, rows, path);
+    } else {
+      best_tight_path(&self.folded, term, config.max_window, &score, rows, path);
+    }
+  }
+
+  fn path_weights(&self) -> Option<PathWeights<'_>> {
+    if self.path {
+      Some(PathWeights {
+        basename_bonuses: &self.basename_bonuses,
+        directory_gaps: &self.directory_gaps,
+      })
+    } else {
+      None
+    }
+  }
+
+  // The cluster of each matched unit, and where the clusters start in the
+  // original candidate
+  fn clusters(&self) -> (&[usize], &[usize]) {
+    if self.graphemes {
+      (&self.segments.clusters, &self.segments.cluster_starts)
+    } else {
+      (&self.clusters, &self.cluster_starts)
+    }
+  }
+}
+
+impl PreparedCandidate {
+  pub(crate) fn new(candidate: &str, config: &ScoringConfig) -> Self {
+    let scheme = &*config.bonus_scheme;
+    let ascii = candidate.is_ascii();
+    let mut folded_bytes = Vec::new();
+    let mut folded = Vec::new();
+    let mut bonuses = Vec::new();
+    let mut basename_bonuses = Vec::new();
+    let mut directory_gaps = Vec::new();
+    if ascii {
+      scheme.ascii_bonuses(candidate.as_bytes(), &mut bonuses);
+      if let Some(ref path) = config.path {
+        path_weights(candidate.as_bytes(), path, &mut basename_bonuses, &mut directory_gaps);
+      }
+      folded_bytes.extend(candidate.bytes().map(|b| b.to_ascii_lowercase()));
+    } else {
+      let chars: Vec<char> = candidate.chars().collect();
+      scheme.bonuses(&chars, &mut bonuses);
+      if let Some(ref path) = config.path {
+        path_weights(&chars, path, &mut basename_bonuses, &mut directory_gaps);
+      }
+      folded.extend(chars.into_iter().map(fold_case));
+    }
+
+    let mut out = Self {
+      ascii,
+      folded_bytes,
+      folded,
+      match_bonuses: Vec::new(),
+      basename_bonuses,
+      directory_gaps,
+      normalized: OnceLock::new(),
+    };
+    match_bonuses(&bonuses, config, &mut out.match_bonuses);
+    out
+  }
+}
+
+impl Normalized {
+  fn new(normalizer: Normalizer, candidate: &str) -> Self {
+    let mut chars = Vec::new();
+    normalizer.normalize_graphemes(candidate, &mut chars);
+    let folded = chars.iter().map(|&c| fold_case(c)).collect();
+    Self {
+      normalizer,
+      chars,
+      folded,
+    }
+  }
+}
+
+// Scores a single (non-inverse) term against a candidate it matches,
+// optionally marking where it matches in `mask`
+fn score_term(
+  term: &Term,
+  candidate: &Candidate,
+  config: &ScoringConfig,
+  scratch: &mut Scratch,
+  mask: Option<&mut BitVec>,
+) -> Score {
+  let t_len = term.len();
+  let c_len = candidate.len();
+
+  if t_len == c_len {
+    // The term matches, so equal lengths mean equal strings
+    if let Some(mask) = mask {
+      mask.set_all();
+    }
+    return SCORE_MAX;
+  }
+
+  if term.kind() == TermKind::Fuzzy {
+    return score_fuzzy(term, candidate, config, scratch, mask);
+  }
+
+  if candidate.ascii {
+    term.exact_starts(&candidate.bytes, &mut scratch.starts);
+  } else if candidate.graphemes {
+    term.exact_starts(&candidate.segments.ids, &mut scratch.starts);
+  } else {
+    term.exact_starts(&candidate.folded, &mut scratch.starts);
+  }
+
+  let (start, score) = scratch
+    .starts
+    .iter()
+    .map(|&start| (start, score_run(start, t_len, candidate, config)))
+    .fold((0, SCORE_MIN), |best, this| if this.1 > best.1 { this } else { best });
+  if let Some(mask) = mask {
+    for j in start..start + t_len {
+      mask.set(j, true);
+    }
+  }
+  score
+}
+
+fn score_fuzzy(
+  term: &Term,
+  candidate: &Candidate,
+  config: &ScoringConfig,
+  scratch: &mut Scratch,
+  mask: Option<&mut BitVec>,
+) -> Score {
+  let c_len = candidate.len();
+  if c_len <= config.max_window {
+    return score_window(term, candidate, config, 0..c_len, scratch, mask);
+  }
+
+  // Too long to score in full, so find the best of the tightest matches and
+  // only score a window around it, leaving as much room before it as after
+  // for the full scoring to find a better match nearby
+  candidate.best_tight_path(term, config, scratch);
+  let first = scratch.path[0];
+  let last = scratch.path[scratch.path.len() - 1];
+  if last - first < config.max_window {
+    let room = config.max_window - (last - first + 1);
+    let end = (last + 1 + room - room / 2).min(c_len);
+    let start = end.saturating_sub(config.max_window);
+    return score_window(term, candidate, config, start..end, scratch, mask);
+  }
+
+  // Even the tightest match doesn't fit in a window, so settle for it
+  if let Some(mask) = mask {
+    for &j in &scratch.path {
+      mask.set(j, true);
+    }
+  }
+  score_path(&scratch.path, candidate, config)
+}
+
+// Scores a fuzzy term against the `window` of a candidate, as though the rest
+// of the candidate were all gaps
+fn score_window(
+  term: &Term,
+  candidate: &Candidate,
+  config: &ScoringConfig,
+  window: Range<usize>,
+  scratch: &mut Scratch,
+  mask: Option<&mut BitVec>,
+) -> Score {
+  let t_len = term.len();
+  let w_len = window.len();
+  let outside = (window.start as f64 * config.gap_leading)
+    + ((candidate.len() - window.end) as f64 * config.gap_trailing);
+
+  let mask = match mask {
+    Some(mask) => mask,
+    None => {
+      // Only the previous row is needed to calculate the next one
+      fill_term(term, candidate, config, window, 2, scratch);
+      let last_row = (t_len - 1) % 2;
+      return scratch.best_score_overall[last_row * w_len + w_len - 1] + outside;
+    }
+  };
+
+  // Keep every row so that the optimal path can be traced back
+  fill_term(term, candidate, config, window.clone(), t_len, scratch);
+
+  let w_ending = &scratch.best_score_w_ending;
+  let mut match_required = false;
+  let mut j = w_len;
+  for i in (0..t_len).rev() {
+    while j > 0 {
+      j -= 1;
+      let here = w_ending[i * w_len + j];
+      if here != SCORE_MIN && (match_required || here == scratch.best_score_overall[i * w_len + j]) {
+        // There's a match here that was on an optimal path, and if it was
+        // reached by a consecutive match the previous letter must match
+        // directly before it
+        match_required = i > 0 && scratch.consecutive[i * w_len + j];
+        mask.set(window.start + j, true);
+        break; // Go to the next query letter
+      }
+    }
+  }
+
+  scratch.best_score_overall[t_len * w_len - 1] + outside
+}
+
+// Score of a fuzzy term matched at the given positions
+fn score_path(path: &[usize], candidate: &Candidate, config: &ScoringConfig) -> Score {
+  let match_bonuses = &candidate.match_bonuses;
+  let weights = candidate.path_weights();
+  let basename = |j: usize| weights.map_or(0.0, |weights| weights.basename_bonuses[j]);
+  let directories = |gap: Range<usize>| {
+    weights.map_or(0.0, |weights| weights.directory_gaps[gap].iter().sum())
+  };
+
+  let first = path[0];
+  let last = path[path.len() - 1];
+  let mut score = (first as f64 * config.gap_leading) + match_bonuses[first] + basename(first);
+  for pair in path.windows(2) {
+    let (previous, j) = (pair[0], pair[1]);
+    score += if j == previous + 1 {
+      match_bonuses[j].max(config.match_consecutive)
+    } else {
+      ((j - previous - 1) as f64 * config.gap_inner)
+        + directories(previous + 1..j)
+        + match_bonuses[j]
+    } + basename(j);
+  }
+  score + ((candidate.len() - last - 1) as f64 * config.gap_trailing)
+}
+
+// Score of an exact term matched consecutively from `start`
+fn score_run(start: usize, len: usize, candidate: &Candidate, config: &ScoringConfig) -> Score {
+  let basename = candidate.path_weights().map_or(0.0, |weights| {
+    weights.basename_bonuses[start..start + len].iter().sum()
+  });
+  (start as f64 * config.gap_leading)
+    + candidate.match_bonuses[start]
+    + ((len - 1) as f64 * config.match_consecutive)
+    + ((candidate.len() - start - len) as f64 * config.gap_trailing)
+    + basename
+}
+
+fn fill_term(
+  term: &Term,
+  candidate: &Candidate,
+  config: &ScoringConfig,
+  window: Range<usize>,
+  rows: usize,
+  scratch: &mut Scratch,
+) {
+  let bonuses = &candidate.match_bonuses[window.clone()];
+  let path = candidate.path_weights().map(|path| PathWeights {
+    basename_bonuses: &path.basename_bonuses[window.clone()],
+    directory_gaps: &path.directory_gaps[window.clone()],
+  });
+  if candidate.ascii {
+    fill(&candidate.bytes[window], term, bonuses, path, config, rows, scratch);
+  } else if candidate.graphemes {
+    fill(&candidate.segments.ids[window], term, bonuses, path, config, rows, scratch);
+  } else {
+    fill(&candidate.folded[window], term, bonuses, path, config, rows, scratch);
+  }
+}
+
+// Finds the positions of the best scoring of the tightest matches of a fuzzy
+// term ending at each position, preferring those that fit in `max_window`
+//
+// This takes one pass over the candidate, keeping in `rows` the tightest
+// match so far of each prefix of the term: the one that starts latest. It isn't
+// linear in the term though: each character of the candidate that matches the
+// `i`th character of the term copies the `i` positions before it, so it takes
+// O(n·t²) time for a candidate of length n and a term of length t, and every
+// complete match found is scored with `score`, which is O(t) (plus the length
+// of the match in path mode). Only candidates longer than `max_window` get
+// here, and for the short terms of a query that is still far less than
+// scoring the whole candidate would take.
+fn best_tight_path<U: MatchUnit>(
+  candidate: &[U],
+  term: &Term,
+  max_window: usize,
+  score: &dyn Fn(&[usize]) -> Score,
+  rows: &mut Vec<usize>,
+  path: &mut Vec<usize>,
+) {
+  let needle = U::needle(term);
+  let t_len = needle.len();
+  rows.clear();
+  rows.resize(t_len * t_len, usize::MAX);
+  path.clear();
+
+  let mut best = (false, SCORE_MIN);
+  for (j, c) in candidate.iter().enumerate() {
+    // Later prefixes first, so that each extends a match ending before `j`
+    for i in (0..t_len).rev() {
+      if needle[i] != *c {
+        continue;
+      }
+      if i > 0 {
+        let previous = (i - 1) * t_len;
+        if rows[previous] == usize::MAX {
+          continue;
+        }
+        rows.copy_within(previous..previous + i, i * t_len);
+      }
+      rows[i * t_len + i] = j;
+
+      if i == t_len - 1 {
+        let found = &rows[i * t_len..];
+        let this = (j - found[0] < max_window, score(found));
+        if path.is_empty() || (this.0 && !best.0) || (this.0 == best.0 && this.1 > best.1) {
+          best = this;
+          path.clear();
+          path.extend_from_slice(found);
+        }
+      }
+    }
+  }
+}
+
+// Runs the scoring of a fuzzy term over a folded candidate, keeping `rows`
+// rows of the matrices (term row `i` is stored in row `i % rows`). Generic so
+// that ASCII candidates can be scored byte-by-byte.
+fn fill<U: MatchUnit>(
+  candidate: &[U],
+  term: &Term,
+  match_bonuses: &[Score],
+  path: Option<PathWeights>,
+  config: &ScoringConfig,
+  rows: usize,
+  scratch: &mut Scratch,
+) {
+  let query = U::needle(term);
+  let q_len = query.len();
+  let c_len = candidate.len();
+  let best_score_w_ending = &mut scratch.best_score_w_ending;
+  let best_score_overall = &mut scratch.best_score_overall;
+  let consecutive = &mut scratch.consecutive;
+
+  if best_score_overall.len() < rows * c_len {
+    best_score_w_ending.resize(rows * c_len, SCORE_MIN);
+    best_score_overall.resize(rows * c_len, SCORE_MIN);
+    consecutive.resize(rows * c_len, false);
+  }
+
+  for (i, q_char) in query.iter().enumerate() {
+    let row = (i % rows) * c_len;
+    let prev_row = (i.wrapping_sub(1) % rows) * c_len;
+    let mut prev_score = SCORE_MIN;
+    let last = i == q_len - 1;
+    let gap_score = if last {
+      config.gap_trailing
+    } else {
+      config.gap_inner
+    };
+
+    for (j, c_char) in candidate.iter().enumerate() {
+      // Path mode penalises directories between matches
+      let directory_gap = match path {
+        Some(path) if !last => path.directory_gaps[j],
+        _ => 0.0,
+      };
+
+      if q_char == c_char {
+        // Get the score bonus for matching this char
+        let (score, run) = if i == 0 {
+          // Beginning of the query, penalty for leading gap
+          ((j as f64 * config.gap_leading) + match_bonuses[j], false)
+        } else if j != 0 {
+          // Middle of both query and candidate
+          // Either give it the match bonus, or use the consecutive
+          // match (which wil always be higher, but doesn't stack
+          // with match bonus)
+          let bonus = best_score_overall[prev_row + j - 1] + match_bonuses[j];
+          let run = best_score_w_ending[prev_row + j - 1] + config.match_consecutive;
+          if run >= bonus {
+            (run, true)
+          } else {
+            (bonus, false)
+          }
+        } else {
+          (SCORE_MIN, false)
+        };
+        let score = match path {
+          Some(path) => score + path.basename_bonuses[j],
+          None => score,
+        };
+
+        prev_score = score.max(prev_score + gap_score + directory_gap);
+        best_score_overall[row + j] = prev_score;
+        best_score_w_ending[row + j] = score;
+        consecutive[row + j] = run;
+      } else {
+        // Give the score penalty for the gap
+        prev_score += gap_score + directory_gap;
+        best_score_overall[row + j] = prev_score;
+        // We don't end in a match
+        best_score_w_ending[row + j] = SCORE_MIN;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use score::config::PathScoring;
+  use score::{has_match, has_match_ascii, has_match_unicode, CaseMatching, Normalization, QueryOptions};
+
+  // Small deterministic generator, so that the comparisons are repeatable
+  struct XorShift(u64);
+
+  impl XorShift {
+    fn next(&mut self) -> u64 {
+      self.0 ^= self.0 << 13;
+      self.0 ^= self.0 >> 7;
+      self.0 ^= self.0 << 17;
+      self.0
+    }
+
+    fn string(&mut self, alphabet: &[u8], max_len: usize) -> String {
+      let len = self.next() as usize % (max_len + 1);
+      (0..len)
+        .map(|_| alphabet[self.next() as usize % alphabet.len()] as char)
+        .collect()
+    }
+  }
+
+  fn matrices(query: &Query, candidate: &str, ascii: bool) -> (Vec<Score>, Vec<Score>) {
+    let config = ScoringConfig::default();
+    let term = query.fuzzy_term().unwrap();
+    let mut bonuses = Vec::new();
+    let mut match_bonuses = Vec::new();
+    let mut scratch = Scratch::default();
+
+    if ascii {
+      config.bonus_scheme.ascii_bonuses(candidate.as_bytes(), &mut bonuses);
+      super::match_bonuses(&bonuses, &config, &mut match_bonuses);
+      let folded: Vec<u8> = candidate.bytes().map(|b| query.fold_byte(b)).collect();
+      fill(&folded, term, &match_bonuses, None, &config, term.len(), &mut scratch);
+    } else {
+      let chars: Vec<char> = candidate.chars().collect();
+      config.bonus_scheme.bonuses(&chars, &mut bonuses);
+      super::match_bonuses(&bonuses, &config, &mut match_bonuses);
+      let folded: Vec<char> = chars.iter().map(|&c| query.fold(c)).collect();
+      fill(&folded, term, &match_bonuses, None, &config, term.len(), &mut scratch);
+    }
+
+    (scratch.best_score_w_ending, scratch.best_score_overall)
+  }
+
+  #[test]
+  fn ascii_path_identical() {
+    let alphabet = b"aAbBcCxX/.-_ *";
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+
+    for i in 0..5_000 {
+      let case = if i % 2 == 0 {
+        CaseMatching::Smart
+      } else {
+        CaseMatching::Insensitive
+      };
+      let query = Query::with_case(&rng.string(alphabet, 4), case);
+      let candidate = rng.string(alphabet, 24);
+
+      let term = match query.fuzzy_term() {
+        Some(term) => term,
+        None => continue,
+      };
+
+      let ascii_match = has_match_ascii(&query, term, candidate.as_bytes());
+      let unicode_match = has_match_unicode(&query, term, &candidate);
+      assert_eq!(unicode_match, ascii_match, "{:?} {:?}", query, candidate);
+
+      assert_eq!(
+        matrices(&query, &candidate, false),
+        matrices(&query, &candidate, true),
+        "{:?} {:?}",
+        query,
+        candidate
+      );
+    }
+  }
+
+  #[test]
+  fn reuse_matches_fresh() {
+    let config = ScoringConfig::default();
+    let candidates = [
+      "app/models/order",
+      "a",
+      "app models-order",
+      "amor",
+      "😨aAmo♺r·®x¯ÍĞ.ɅƁñîƹ♺àwÑ☆ǈ😞´ƙºÑ♫",
+      "*a*m*o*r*",
+    ];
+
+    let query = Query::new("amor");
+    let mut matcher = Matcher::new(&query, &config);
+    for candidate in candidates.iter().chain(candidates.iter().rev()) {
+      let mut fresh = Matcher::new(&query, &config);
+      assert_eq!(fresh.score(candidate).score, matcher.score(candidate).score);
+
+      let mut fresh = Matcher::new(&query, &config);
+      assert_eq!(
+        fresh.locate(candidate).match_mask,
+        matcher.locate(candidate).match_mask
+      );
+    }
+  }
+
+  #[test]
+  fn score_matches_locate() {
+    let config = ScoringConfig::default();
+    let mut matcher = Matcher::new(&Query::new("abc"), &config);
+    for candidate in &["abc", "a-b-c", "xaxbxcx", "ABC/abc", "a/b/c/abc"] {
+      assert_eq!(matcher.score(candidate).score, matcher.locate(candidate).score);
+    }
+  }
+
+  #[test]
+  fn locate_mask() {
+    let config = ScoringConfig::default();
+    let mut matcher = Matcher::new(&Query::new("amo"), &config);
+    let result = matcher.locate("app/models/foo");
+    let positions: Vec<usize> = (0..result.match_mask.len())
+      .filter(|&i| result.match_mask[i])
+      .collect();
+    assert_eq!(vec![0, 4, 5], positions);
+  }
+
+  #[test]
+  fn locate_traces_back_runs() {
+    // The best match ends with a consecutive run, which has to be traced back
+    // as a run even where matching the letter earlier scores better so far
+    let config = ScoringConfig::default();
+    let query = Query::new("amor");
+    let mut matcher = Matcher::new(&query, &config);
+    for &(candidate, expected) in &[
+      ("app/models/order", [0, 4, 11, 12]),
+      ("a/mo/x/or", [0, 2, 7, 8]),
+    ] {
+      let result = matcher.locate(candidate);
+      let positions = positions(&result);
+      assert_eq!(expected.to_vec(), positions, "{}", candidate);
+
+      // The located positions score what the search says they do
+      assert!(matcher.prepare(candidate));
+      assert_close(score_path(&positions, &matcher.candidate, &config), result.score);
+    }
+  }
+
+  #[test]
+  fn locate_path_traces_back_runs() {
+    // Path mode adds the basename bonus to runs too, which the traceback has
+    // to follow as the scoring did
+    let config = ScoringConfig {
+      path: Some(PathScoring::default()),
+      ..ScoringConfig::default()
+    };
+    let mut matcher = Matcher::new(&Query::new("bc"), &config);
+    assert_eq!(vec![7, 8], positions(&matcher.locate("xx/bababccx")));
+
+    let part_score = |part: &ScorePart| match *part {
+      ScorePart::Match { score, .. }
+      | ScorePart::Gap { score, .. }
+      | ScorePart::Basename { score, .. } => score,
+    };
+    let alphabet = b"abcx./";
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+    let mut cases = vec![("cba".to_string(), ".caxcac./bbcaba.c".to_string())];
+    cases.extend((0..5_000).map(|_| (rng.string(alphabet, 3), rng.string(alphabet, 20))));
+    for (query, candidate) in &cases {
+      let query = Query::new(query);
+      if query.is_empty() || !has_match(&query, candidate) {
+        continue;
+      }
+      let mut matcher = Matcher::new(&query, &config);
+      let score = matcher.score(candidate).score;
+      let result = matcher.locate(candidate);
+      assert_eq!(score, result.score);
+      if score == SCORE_MAX {
+        continue;
+      }
+
+      let explanation = matcher.explain(candidate);
+      let parts: Score = explanation.terms[0].parts.iter().map(part_score).sum();
+      assert_close(score, parts);
+
+      let positions = positions(&result);
+      assert!(matcher.prepare(candidate));
+      assert_close(score, score_path(&positions, &matcher.candidate, &config));
+    }
+  }
+
+  fn extended(query: &str) -> Query {
+    Query::with_options(
+      query,
+      &QueryOptions {
+        extended: true,
+        ..QueryOptions::default()
+      },
+    )
+  }
+
+  fn positions(result: &LocateResult) -> Vec<usize> {
+    (0..result.match_mask.len())
+      .filter(|&i| result.match_mask[i])
+      .collect()
+  }
+
+  #[test]
+  fn extended_terms_add_up() {
+    let config = ScoringConfig::default();
+    let candidate = "src/score/matcher.rs";
+    let fuzzy = Matcher::new(&Query::new("mat"), &config).score(candidate).score;
+    let suffix = Matcher::new(&extended(".rs$"), &config).score(candidate).score;
+    let both = Matcher::new(&extended("mat .rs$"), &config).score(candidate).score;
+    assert_eq!(fuzzy + suffix, both);
+
+    assert_eq!(SCORE_GAP_LEADING * 17.0 + SCORE_MATCH_CONSECUTIVE * 2.0, suffix);
+    assert_eq!(
+      SCORE_GAP_LEADING * 4.0
+        + SCORE_MATCH_SLASH
+        + SCORE_MATCH_CONSECUTIVE * 4.0
+        + SCORE_GAP_TRAILING * 11.0,
+      Matcher::new(&extended("'score"), &config).score(candidate).score
+    );
+    assert_eq!(
+      SCORE_MAX,
+      Matcher::new(&extended("^src/score/matcher.rs$"), &config).score(candidate).score
+    );
+  }
+
+  #[test]
+  fn extended_locate() {
+    let config = ScoringConfig::default();
+    let mut matcher = Matcher::new(&extended("^src mat rs$ !foo"), &config);
+    let result = matcher.locate("src/score/matcher.rs");
+    assert_eq!(vec![0, 1, 2, 10, 11, 12, 18, 19], positions(&result));
+    assert_eq!(matcher.score("src/score/matcher.rs").score, result.score);
+
+    // The best of the alternatives is used
+    let mut matcher = Matcher::new(&extended("'foo | 'ore | zzz"), &config);
+    let result = matcher.locate("src/score/matcher.rs");
+    assert_eq!(vec![6, 7, 8], positions(&result));
+    assert_eq!(matcher.score("src/score/matcher.rs").score, result.score);
+
+    let mut matcher = Matcher::new(&extended("'e | 'sc"), &config);
+    assert_eq!(vec![4, 5], positions(&matcher.locate("src/score/matcher.rs")));
+
+    // Exact terms take their best placement
+    let mut matcher = Matcher::new(&extended("'a"), &config);
+    assert_eq!(vec![4], positions(&matcher.locate("bab/a")));
+  }
+
+  #[test]
+  fn extended_only_inverse() {
+    let config = ScoringConfig::default();
+    let mut matcher = Matcher::new(&extended("!foo"), &config);
+    assert_eq!(SCORE_MIN, matcher.score("bar").score);
+    assert!(positions(&matcher.locate("bar")).is_empty());
+  }
+
+  fn normalized(query: &str, normalization: Normalization, strip_diacritics: bool) -> Query {
+    Query::with_options(
+      query,
+      &QueryOptions {
+        normalization,
+        strip_diacritics,
+        ..QueryOptions::default()
+      },
+    )
+  }
+
+  #[test]
+  fn normalized_forms_match() {
+    let config = ScoringConfig::default();
+    let composed = "Müller";
+    let decomposed = "Mu\u{308}ller";
+
+    let query = normalized("mül", Normalization::Nfc, false);
+    assert!(has_match(&query, composed));
+    assert!(has_match(&query, decomposed));
+    assert!(!has_match(&Query::new("mül"), decomposed));
+
+    let mut matcher = Matcher::new(&query, &config);
+    let composed_result = matcher.locate(composed);
+    let decomposed_result = matcher.locate(decomposed);
+    assert_eq!(composed_result.score, decomposed_result.score);
+    assert_eq!(matcher.score(decomposed).score, decomposed_result.score);
+
+    // Masks are in terms of the original characters
+    assert_eq!(6, composed_result.match_mask.len());
+    assert_eq!(vec![0, 1, 2], positions(&composed_result));
+    assert_eq!(7, decomposed_result.match_mask.len());
+    assert_eq!(vec![0, 1, 2, 3], positions(&decomposed_result));
+  }
+
+  #[test]
+  fn stripped_diacritics_match() {
+    let config = ScoringConfig::default();
+    let query = normalized("cafe", Normalization::Nfc, true);
+    assert!(has_match(&query, "café"));
+    assert!(has_match(&query, "cafe\u{301}"));
+
+    let mut matcher = Matcher::new(&query, &config);
+    let result = matcher.locate("le cafe\u{301}!");
+    assert_eq!(vec![3, 4, 5, 6, 7], positions(&result));
+    assert_eq!(matcher.locate("le café!").score, result.score);
+
+    let query = normalized("fiance", Normalization::Nfkc, true);
+    let mut matcher = Matcher::new(&query, &config);
+    let result = matcher.locate("ﬁancée");
+    assert_eq!(vec![0, 1, 2, 3, 4], positions(&result));
+
+    // The accented query is stripped too
+    let query = normalized("Müll", Normalization::None, true);
+    assert!(has_match(&query, "Muller"));
+    assert!(!has_match(&query, "Mallet"));
+  }
+
+  // Scores summed in a different order may differ by rounding
+  fn assert_close(expected: Score, actual: Score) {
+    assert!((expected - actual).abs() < 1e-9, "{} != {}", expected, actual);
+  }
+
+  #[test]
+  fn long_candidates() {
+    let config = ScoringConfig::default();
+    let unlimited = ScoringConfig {
+      max_window: usize::MAX,
+      ..ScoringConfig::default()
+    };
+    let candidate = format!("{}app/models/order{}", "x".repeat(3000), "y".repeat(3000));
+    let query = Query::new("amor");
+
+    let mut matcher = Matcher::new(&query, &config);
+    let result = matcher.locate(&candidate);
+    assert!(result.score > SCORE_MIN);
+    assert_eq!(vec![3000, 3004, 3011, 3012], positions(&result));
+    assert_eq!(matcher.score(&candidate).score, result.score);
+
+    let mut full = Matcher::new(&query, &unlimited);
+    assert_close(full.score(&candidate).score, result.score);
+    assert_eq!(full.locate(&candidate).match_mask, result.match_mask);
+
+    // Still found in a long non-ASCII candidate
+    let candidate = format!("{}amor", "ɅƁ ".repeat(1000));
+    let result = matcher.locate(&candidate);
+    assert_close(full.score(&candidate).score, result.score);
+    assert_eq!(vec![3000, 3001, 3002, 3003], positions(&result));
+  }
+
+  #[test]
+  fn best_window() {
+    let config = ScoringConfig::default();
+    let unlimited = ScoringConfig {
+      max_window: usize::MAX,
+      ..ScoringConfig::default()
+    };
+    let query = Query::new("amor");
+    let mut matcher = Matcher::new(&query, &config);
+    let mut full = Matcher::new(&query, &unlimited);
+
+    // A spread out match comes first, but the best one is further on
+    let x = "x".repeat(300);
+    let candidate = format!("a{}m{}o{}r{}amor", x, x, x, "y".repeat(2000));
+    let result = matcher.locate(&candidate);
+    assert_eq!(vec![2904, 2905, 2906, 2907], positions(&result));
+    assert_close(full.score(&candidate).score, result.score);
+    assert_eq!(matcher.score(&candidate).score, result.score);
+
+    // Wherever the best match fits in the window, it's what's found
+    let decoys = ["", "a-m-o-r", "am/or ", "a_m_o_r_", "Amo-r"];
+    for (d, decoy) in decoys.iter().enumerate() {
+      for &gap in &[0, 10, 900, 1500] {
+        for best in &["amor", "app/models/order", "AMOR", "a/m/o/r"] {
+          let candidate = format!(
+            "{}{}{}{}{}",
+            "-".repeat(1100 + d * 7),
+            decoy,
+            ".".repeat(gap),
+            best,
+            "=".repeat(1100)
+          );
+          let expected = full.locate(&candidate);
+          let first = expected.match_mask.iter().position(|m| m).unwrap();
+          let last = expected.match_mask.iter().rposition(|m| m).unwrap();
+          assert!(last - first < config.max_window);
+          let result = matcher.locate(&candidate);
+          assert_eq!(expected.match_mask, result.match_mask, "{:?}", (decoy, gap, best));
+          assert_close(expected.score, result.score);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn match_wider_than_window() {
+    let narrow = ScoringConfig {
+      max_window: 4,
+      ..ScoringConfig::default()
+    };
+    let config = ScoringConfig::default();
+    let candidate = "--a-----b/cd--";
+    let query = Query::new("abc");
+
+    let mut matcher = Matcher::new(&query, &narrow);
+    let result = matcher.locate(candidate);
+    assert_eq!(vec![2, 8, 10], positions(&result));
+    assert_eq!(matcher.score(candidate).score, result.score);
+    // There is only one way to match, which is what the full scoring finds
+    assert_close(Matcher::new(&query, &config).score(candidate).score, result.score);
+
+    // Consecutive matches still get their bonus
+    let mut matcher = Matcher::new(&Query::new("acd"), &narrow);
+    assert_close(
+      Matcher::new(&Query::new("acd"), &config).score(candidate).score,
+      matcher.score(candidate).score,
+    );
+  }
+}
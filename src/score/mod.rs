@@ -1,32 +1,85 @@
 extern crate bit_vec;
-extern crate ndarray;
+extern crate memchr;
 
+mod bonus;
 pub mod config;
+mod explain;
+mod grapheme;
+mod matcher;
+mod normalize;
+mod query;
 
 use std::cmp::Ordering;
+use std::ops::Range;
 
 use self::bit_vec::BitVec;
-use self::ndarray::prelude::*;
+use self::memchr::{memchr, memchr2};
 
 use self::config::*;
+use self::grapheme::Segments;
+pub use self::bonus::{
+  Bonus, BonusScheme, DefaultBonuses, RustPathBonuses, UrlBonuses, WindowsPathBonuses,
+};
+pub use self::explain::{Explanation, Gap, ScorePart, TermExplanation};
+pub use self::matcher::Matcher;
+pub(crate) use self::matcher::PreparedCandidate;
+pub use self::normalize::Normalization;
+pub use self::query::{CaseMatching, Query, QueryOptions};
+use self::query::{MatchUnit, Term};
 
 pub type Score = f64;
-type ScoreMatrix = Array2<Score>;
 
 /// Result of querying the score against a candidate
-#[derive(Debug)]
+///
+/// Results are ordered best first: by descending score, then by the
+/// `ScoringConfig::tie_breaks` they were scored with, then by
+/// `candidate_index`.
+#[derive(Clone, Debug)]
 pub struct ScoreResult {
   pub candidate_index: usize,
   pub score: Score,
+  ties: Ties,
 }
 
 /// Result of querying the score and location against a candidate
-#[derive(Debug)]
+///
+/// Results are ordered as `ScoreResult`s are.
+#[derive(Clone, Debug)]
 pub struct LocateResult {
   pub candidate_index: usize,
   pub score: Score,
   /// Binary mask showing where the charcaters of the query match the candidate
   pub match_mask: BitVec,
+  ties: Ties,
+}
+
+// Keys for ordering results with equal scores, one per distinct tie break
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Ties([usize; 3]);
+
+impl Ties {
+  // Keys for a candidate of `len` characters, and `span` characters from its
+  // first match to its last if they are known
+  fn new(tie_breaks: &[TieBreak], candidate_index: usize, len: usize, span: Option<usize>) -> Self {
+    let mut keys = [0; 3];
+    // One bit for each tie break seen, so that only the first of each counts
+    let mut seen = 0u8;
+    let mut count = 0;
+    for &tie_break in tie_breaks {
+      let bit = 1 << tie_break as u8;
+      if seen & bit != 0 {
+        continue;
+      }
+      seen |= bit;
+      keys[count] = match tie_break {
+        TieBreak::Length => len,
+        TieBreak::Index => candidate_index,
+        TieBreak::Span => span.unwrap_or(0),
+      };
+      count += 1;
+    }
+    Ties(keys)
+  }
 }
 
 impl ScoreResult {
@@ -38,28 +91,41 @@ impl ScoreResult {
     Self {
       candidate_index,
       score,
+      ties: Ties::default(),
     }
   }
+
+  // Breaks ties with other results for a candidate of `len` characters, with
+  // `span` characters from its first match to its last if they were located
+  pub(crate) fn break_ties(&mut self, tie_breaks: &[TieBreak], len: usize, span: Option<usize>) {
+    self.ties = Ties::new(tie_breaks, self.candidate_index, len, span);
+  }
+}
+
+impl Ord for ScoreResult {
+  fn cmp(&self, other: &Self) -> Ordering {
+    other
+      .score
+      .total_cmp(&self.score)
+      .then(self.ties.cmp(&other.ties))
+      .then(self.candidate_index.cmp(&other.candidate_index))
+  }
 }
 
 impl PartialOrd for ScoreResult {
   fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-    Some(
-      self
-        .score
-        .partial_cmp(&other.score)
-        .unwrap_or(Ordering::Less)
-        .reverse(),
-    )
+    Some(self.cmp(other))
   }
 }
 
 impl PartialEq for ScoreResult {
   fn eq(&self, other: &Self) -> bool {
-    self.score == other.score
+    self.cmp(other) == Ordering::Equal
   }
 }
 
+impl Eq for ScoreResult {}
+
 impl LocateResult {
   pub fn new(candidate_index: usize, candidate_size: usize) -> Self {
     Self::with_score(candidate_index, candidate_size, SCORE_MIN)
@@ -68,226 +134,217 @@ impl LocateResult {
   pub fn with_score(candidate_index: usize, candidate_size: usize, score: Score) -> Self {
     Self {
       candidate_index,
-      score: score,
+      score,
       match_mask: BitVec::from_elem(candidate_size, false),
+      ties: Ties::default(),
+    }
+  }
+
+  // Breaks ties with other results, once the match mask is filled
+  pub(crate) fn break_ties(&mut self, tie_breaks: &[TieBreak]) {
+    let mut matched = (0..self.match_mask.len()).filter(|&i| self.match_mask[i]);
+    let span = matched.next().map(|first| matched.next_back().unwrap_or(first) - first + 1);
+    let len = self.match_mask.len();
+    self.ties = Ties::new(tie_breaks, self.candidate_index, len, span);
+  }
+
+  /// The positions of the matched characters of `candidate`, which must be
+  /// the candidate this result is for, as `(char_index, byte_index)` pairs
+  pub fn match_indices<'a>(&'a self, candidate: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+    candidate
+      .char_indices()
+      .enumerate()
+      .filter(move |&(i, _)| self.is_matched(i))
+      .map(|(i, (byte, _))| (i, byte))
+  }
+
+  /// The byte ranges of the contiguous runs of matched characters of
+  /// `candidate`, which must be the candidate this result is for
+  ///
+  /// These can be used to slice the candidate directly, e.g. for highlighting.
+  pub fn match_ranges(&self, candidate: &str) -> Vec<Range<usize>> {
+    let mut out: Vec<Range<usize>> = Vec::new();
+    for (i, (byte, character)) in candidate.char_indices().enumerate() {
+      if !self.is_matched(i) {
+        continue;
+      }
+      let end = byte + character.len_utf8();
+      match out.last_mut() {
+        Some(range) if range.end == byte => range.end = end,
+        _ => out.push(byte..end),
+      }
     }
+    out
+  }
+
+  fn is_matched(&self, char_index: usize) -> bool {
+    self.match_mask.get(char_index).unwrap_or(false)
+  }
+}
+
+impl Ord for LocateResult {
+  fn cmp(&self, other: &Self) -> Ordering {
+    other
+      .score
+      .total_cmp(&self.score)
+      .then(self.ties.cmp(&other.ties))
+      .then(self.candidate_index.cmp(&other.candidate_index))
   }
 }
 
 impl PartialOrd for LocateResult {
   fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-    Some(
-      self
-        .score
-        .partial_cmp(&other.score)
-        .unwrap_or(Ordering::Less)
-        .reverse(),
-    )
+    Some(self.cmp(other))
   }
 }
 
 impl PartialEq for LocateResult {
   fn eq(&self, other: &Self) -> bool {
-    self.score == other.score
+    self.cmp(other) == Ordering::Equal
   }
 }
 
+impl Eq for LocateResult {}
+
 /// Returns `true` if and only if `candidate` is a match for `query`
 ///
 /// A "match" must contain all of the letters of `query` in order, but not
-/// necessarily continguously.
-pub fn has_match(query: &str, candidate: &str) -> bool {
-  let mut cand_iter = candidate.chars();
-  // Note: `cand_iter` will be advanced during `all`, which is short-circuiting
-  query
-    .chars()
-    .all(|c| cand_iter.any(|c2| c2.to_lowercase().eq(c.to_lowercase())))
-}
-
-/// Calculates a score for how well a `query` matches a `candidate`
-///
-/// Higher scores are better
-pub fn score(query: &str, candidate: &str) -> ScoreResult {
-  score_inner(query, candidate, 0)
-}
-
-pub(crate) fn score_inner(query: &str, candidate: &str, index: usize) -> ScoreResult {
-  let (q_len, c_len) = match get_lengths(query, candidate) {
-    LengthsOrScore::Score(s) => return ScoreResult::with_score(index, s),
-    LengthsOrScore::Lengths(q, c) => (q, c),
-  };
-
-  let (best_score_overall, _) = score_internal(query, candidate, q_len, c_len);
-  ScoreResult::with_score(index, best_score_overall[[q_len - 1, c_len - 1]])
-}
-
-/// Calculates a score for how well a `query` matches a `candidate` and gives
-/// the locations of the `query` characters in the `candidate` too
-///
-/// Higher scores are better
-pub fn locate(query: &str, candidate: &str) -> LocateResult {
-  locate_inner(query, candidate, 0)
-}
-
-pub(crate) fn locate_inner(query: &str, candidate: &str, index: usize) -> LocateResult {
-  let candidate_chars = candidate.chars().count();
-  let (q_len, c_len) = match get_lengths(query, candidate) {
-    LengthsOrScore::Score(s) => {
-      let mut out = LocateResult::with_score(index, candidate_chars, s);
-      if s == SCORE_MAX {
-        // This was an exact match
-        out.match_mask.set_all();
-      }
-      return out;
+/// necessarily continguously. With the extended syntax, every group of terms
+/// must have a matching term instead.
+pub fn has_match(query: &Query, candidate: &str) -> bool {
+  let ascii = query.is_ascii_match(candidate);
+  if !ascii && query.matches_graphemes() {
+    let mut segments = Segments::default();
+    segments.segment(query, candidate);
+    has_match_groups(query, &segments.ids)
+  } else if !ascii && !query.normalizer().is_identity() {
+    let mut folded = Vec::with_capacity(candidate.len());
+    query.normalizer().normalize_graphemes(candidate, &mut folded);
+    for c in &mut folded {
+      *c = query.fold(*c);
     }
-    LengthsOrScore::Lengths(q, c) => (q, c),
-  };
-
-  let (best_score_overall, best_score_w_ending) = score_internal(query, candidate, q_len, c_len);
-  let mut out = LocateResult::with_score(index, candidate_chars, best_score_overall[[q_len - 1, c_len - 1]]);
-
-  let mut query_iter = query.chars();
-  let mut cand_iter = candidate.chars();
-  // Safe because we'll return at the beginning for zero or unit length
-  let mut i = q_len;
-  let mut j = c_len;
-  while query_iter.next_back() != None {
-    i = i.wrapping_sub(1);
-    while cand_iter.next_back() != None {
-      j = j.wrapping_sub(1);
-      if best_score_w_ending[[i, j]] != SCORE_MIN
-        && best_score_w_ending[[i, j]] == best_score_overall[[i, j]]
-      {
-        // There's a match here that was on an optimal path
-        out.match_mask.set(j, true);
-        break; // Go to the next query letter
-      }
+    has_match_groups(query, &folded)
+  } else if let Some(term) = query.fuzzy_term() {
+    if ascii {
+      has_match_ascii(query, term, candidate.as_bytes())
+    } else {
+      has_match_unicode(query, term, candidate)
     }
+  } else if ascii {
+    let folded: Vec<u8> = candidate.bytes().map(|b| query.fold_byte(b)).collect();
+    has_match_groups(query, &folded)
+  } else {
+    let folded: Vec<char> = candidate.chars().map(|c| query.fold(c)).collect();
+    has_match_groups(query, &folded)
   }
-
-  out
 }
 
-enum LengthsOrScore {
-  Lengths(usize, usize),
-  Score(self::Score),
+fn has_match_groups<U: MatchUnit>(query: &Query, candidate: &[U]) -> bool {
+  query
+    .groups()
+    .iter()
+    .all(|group| group.iter().any(|term| term.is_match(candidate)))
 }
 
-fn get_lengths(query: &str, candidate: &str) -> LengthsOrScore {
-  if candidate.len() > CANDIDATE_MAX_BYTES || query.len() == 0 {
-    // Candidate too long or query too short
-    return LengthsOrScore::Score(SCORE_MIN);
-  }
-
-  let q_len = query.chars().count();
-  let c_len = candidate.chars().count();
-
-  if q_len == c_len {
-    // This is only called when there _is_ a match (candidate contains all
-    // chars of query in the right order, so equal lengths mean equal
-    // strings
-    return LengthsOrScore::Score(SCORE_MAX);
-  }
-
-  if c_len > CANDIDATE_MAX_CHARS {
-    // Too many characters
-    return LengthsOrScore::Score(SCORE_MIN);
-  }
-
-  LengthsOrScore::Lengths(q_len, c_len)
+fn has_match_unicode(query: &Query, term: &Term, candidate: &str) -> bool {
+  let mut cand_iter = candidate.chars();
+  // Note: `cand_iter` will be advanced during `all`, which is short-circuiting
+  term
+    .folded()
+    .iter()
+    .all(|&c| cand_iter.any(|c2| query.fold(c2) == c))
 }
 
-fn score_internal(
-  query: &str,
-  candidate: &str,
-  q_len: usize,
-  c_len: usize,
-) -> (ScoreMatrix, ScoreMatrix) {
-  let match_bonuses = candidate_match_bonuses(candidate);
-
-  // Matrix of the best score for each position ending in a match
-  let mut best_score_w_ending = ScoreMatrix::zeros((q_len, c_len));
-  // Matrix for the best score for each position.
-  let mut best_score_overall = ScoreMatrix::zeros((q_len, c_len));
-
-  for (i, q_char) in query.chars().enumerate() {
-    let mut prev_score = SCORE_MIN;
-    let gap_score = if i == q_len - 1 {
-      SCORE_GAP_TRAILING
+fn has_match_ascii(query: &Query, term: &Term, candidate: &[u8]) -> bool {
+  let mut remaining = candidate;
+  for &q in term.folded_bytes() {
+    let upper = q.to_ascii_uppercase();
+    let found = if query.is_case_sensitive() || upper == q {
+      memchr(q, remaining)
     } else {
-      SCORE_GAP_INNER
+      memchr2(q, upper, remaining)
     };
-
-    for (j, c_char) in candidate.chars().enumerate() {
-      if q_char.to_lowercase().eq(c_char.to_lowercase()) {
-        // Get the score bonus for matching this char
-        let score = if i == 0 {
-          // Beginning of the query, penalty for leading gap
-          (j as f64 * SCORE_GAP_LEADING) + match_bonuses[j]
-        } else if j != 0 {
-          // Middle of both query and candidate
-          // Either give it the match bonus, or use the consecutive
-          // match (which wil always be higher, but doesn't stack
-          // with match bonus)
-          (best_score_overall[[i - 1, j - 1]] + match_bonuses[j])
-            .max(best_score_w_ending[[i - 1, j - 1]] + SCORE_MATCH_CONSECUTIVE)
-        } else {
-          SCORE_MIN
-        };
-
-        prev_score = score.max(prev_score + gap_score);
-        best_score_overall[[i, j]] = prev_score;
-        best_score_w_ending[[i, j]] = score;
-      } else {
-        // Give the score penalty for the gap
-        prev_score = prev_score + gap_score;
-        best_score_overall[[i, j]] = prev_score;
-        // We don't end in a match
-        best_score_w_ending[[i, j]] = SCORE_MIN;
-      }
+    match found {
+      Some(index) => remaining = &remaining[index + 1..],
+      None => return false,
     }
   }
+  true
+}
 
-  (best_score_overall, best_score_w_ending)
+/// Calculates a score for how well a `query` matches a `candidate`, weighted
+/// by `config`
+///
+/// Higher scores are better
+pub fn score(query: &Query, candidate: &str, config: &ScoringConfig) -> ScoreResult {
+  Matcher::new(query, config).score(candidate)
 }
 
-fn candidate_match_bonuses(candidate: &str) -> Vec<Score> {
-  let mut prev_char = '/';
-  candidate
-    .chars()
-    .map(|current| {
-      let s = character_match_bonus(current, prev_char);
-      prev_char = current;
-      s
-    })
-    .collect()
+/// Calculates a score for how well a `query` matches a `candidate` and gives
+/// the locations of the `query` characters in the `candidate` too, weighted by
+/// `config`
+///
+/// Higher scores are better
+pub fn locate(query: &Query, candidate: &str, config: &ScoringConfig) -> LocateResult {
+  Matcher::new(query, config).locate(candidate)
 }
 
-fn character_match_bonus(current: char, previous: char) -> Score {
-  if current.is_uppercase() && previous.is_lowercase() {
-    SCORE_MATCH_CAPITAL
-  } else {
-    match previous {
-      '/' => SCORE_MATCH_SLASH,
-      '.' => SCORE_MATCH_DOT,
-      _ if is_separator(previous) => SCORE_MATCH_WORD,
-      _ => 0.0,
+/// Explains how the score of a `candidate` for a `query` is reached, weighted
+/// by `config`
+pub fn explain(query: &Query, candidate: &str, config: &ScoringConfig) -> Explanation {
+  Matcher::new(query, config).explain(candidate)
+}
+
+// Fills the extra path mode weights for each position of a candidate: the
+// bonus for a match in the basename, and the penalty for a separator between
+// two matches
+fn path_weights<C>(
+  candidate: &[C],
+  path: &PathScoring,
+  basename_bonuses: &mut Vec<Score>,
+  directory_gaps: &mut Vec<Score>,
+) where
+  C: Copy + Into<char>,
+{
+  let basename_start = candidate
+    .iter()
+    .rposition(|&c| is_path_separator(c.into()))
+    .map_or(0, |separator| separator + 1);
+
+  basename_bonuses.clear();
+  basename_bonuses.resize(basename_start, 0.0);
+  basename_bonuses.resize(candidate.len(), path.match_basename);
+
+  directory_gaps.clear();
+  directory_gaps.extend(candidate.iter().map(|&c| {
+    if is_path_separator(c.into()) {
+      path.gap_directory
+    } else {
+      0.0
     }
-  }
+  }));
 }
 
-fn is_separator(character: char) -> bool {
-  match character {
-    ' ' => true,
-    '-' => true,
-    '_' => true,
-    _ => false,
-  }
+fn is_path_separator(character: char) -> bool {
+  character == '/' || character == '\\'
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use std::sync::Arc;
+
+  fn has_match(query: &str, candidate: &str) -> bool {
+    super::has_match(&Query::new(query), candidate)
+  }
+
+  fn score(query: &str, candidate: &str) -> ScoreResult {
+    super::score(&Query::new(query), candidate, &ScoringConfig::default())
+  }
+
+  fn locate(query: &str, candidate: &str) -> LocateResult {
+    super::locate(&Query::new(query), candidate, &ScoringConfig::default())
+  }
 
   #[test]
   fn exact_match() {
@@ -323,7 +380,75 @@ mod tests {
     assert!(has_match(
       "прописная буква",
       "ПРОПИСНАЯ БУКВА"
-    ))
+    ));
+    // Kelvin sign, whose lowercase is ASCII
+    assert!(has_match("k", "\u{212A}"));
+  }
+
+  #[test]
+  fn case_sensitive_match() {
+    let sensitive = |query| Query::with_case(query, CaseMatching::Sensitive);
+    assert!(super::has_match(&sensitive("Vec"), "Vec<T>"));
+    assert!(!super::has_match(&sensitive("Vec"), "vec![]"));
+    assert!(!super::has_match(&sensitive("vec"), "Vec<T>"));
+    assert!(super::has_match(&sensitive("БУКВА"), "прописная БУКВА"));
+    assert!(!super::has_match(&sensitive("буква"), "прописная БУКВА"));
+
+    let smart = |query| Query::with_case(query, CaseMatching::Smart);
+    assert!(super::has_match(&smart("vec"), "Vec<T>"));
+    assert!(super::has_match(&smart("vec"), "vec![]"));
+    assert!(super::has_match(&smart("Vec"), "Vec<T>"));
+    assert!(!super::has_match(&smart("Vec"), "vec![]"));
+  }
+
+  #[test]
+  fn extended_match() {
+    let extended = |query| {
+      Query::with_options(
+        query,
+        &QueryOptions {
+          extended: true,
+          ..QueryOptions::default()
+        },
+      )
+    };
+    let candidate = "src/score/matcher.rs";
+    assert!(super::has_match(&extended(""), candidate));
+    assert!(super::has_match(&extended("mat .rs$"), candidate));
+    assert!(super::has_match(&extended("^SRC 'score"), candidate));
+    assert!(super::has_match(&extended("!search"), candidate));
+    assert!(super::has_match(&extended("foo | mat"), candidate));
+    assert!(super::has_match(&extended("^src/score/matcher.rs$"), candidate));
+    assert!(!super::has_match(&extended("mat !score"), candidate));
+    assert!(!super::has_match(&extended("'sore"), candidate));
+    assert!(!super::has_match(&extended("^score"), candidate));
+    assert!(!super::has_match(&extended(".rs !.rs$"), candidate));
+    assert!(!super::has_match(&extended("foo | bar"), candidate));
+    assert!(!super::has_match(&extended("^src/score$"), candidate));
+    assert!(super::has_match(&extended("'пис буква$"), "прописная БУКВА"));
+    assert!(!super::has_match(&extended("'пис !буква$"), "прописная БУКВА"));
+  }
+
+  #[test]
+  fn case_sensitive_score() {
+    let config = ScoringConfig::default();
+    let query = Query::with_case("aB", CaseMatching::Sensitive);
+    assert_eq!(
+      SCORE_GAP_LEADING * 3.0 + SCORE_MATCH_CONSECUTIVE,
+      super::score(&query, "*abaB", &config).score
+    );
+    let result = super::locate(&query, "*abaB", &config);
+    assert_eq!(
+      vec![false, false, false, true, true],
+      result.match_mask.iter().collect::<Vec<_>>()
+    );
+
+    let query = Query::with_case("aБ", CaseMatching::Sensitive);
+    let result = super::locate(&query, "*aбaБ", &config);
+    assert_eq!(
+      vec![false, false, false, true, true],
+      result.match_mask.iter().collect::<Vec<_>>()
+    );
   }
 
   #[test]
@@ -617,4 +742,215 @@ mod tests {
     );
   }
 
+  #[test]
+  fn score_custom_config() {
+    let config = ScoringConfig {
+      gap_leading: -1.0,
+      match_slash: 5.0,
+      ..ScoringConfig::default()
+    };
+    let query = Query::new("a");
+    assert_eq!(-1.0 + 5.0, super::score(&query, "/a", &config).score);
+    assert_eq!(-2.0, super::score(&query, "*ba", &config).score);
+    assert_eq!(-1.0 + 5.0, super::locate(&query, "/a", &config).score);
+
+    let config = ScoringConfig {
+      match_capital: 0.0,
+      ..ScoringConfig::default()
+    };
+    assert!(
+      super::score(&Query::new("qart"), "QuArTz", &config).score < score("qart", "QuArTz").score
+    );
+  }
+
+  fn graphemes(query: &str) -> Query {
+    Query::with_options(
+      query,
+      &QueryOptions {
+        graphemes: true,
+        ..QueryOptions::default()
+      },
+    )
+  }
+
+  fn positions(result: &LocateResult) -> Vec<usize> {
+    (0..result.match_mask.len())
+      .filter(|&i| result.match_mask[i])
+      .collect()
+  }
+
+  #[test]
+  fn grapheme_match() {
+    let exotic = "😨Ɣ·®x¯ÍĞ.ɅƁñîƹ♺àwÑ☆ǈ😞´ƙºÑ♫";
+    let config = ScoringConfig::default();
+    for query in &["😨Ɣ·®x¯ÍĞɅƁƹ♺à☆ǈ´ƙÑ♫", "ÑÎ", "☆ǈ😞", exotic] {
+      // Every cluster of these is a single character
+      assert!(super::has_match(&graphemes(query), exotic));
+      assert_eq!(
+        super::score(&Query::new(query), exotic, &config).score,
+        super::score(&graphemes(query), exotic, &config).score
+      );
+    }
+    assert!(!super::has_match(&graphemes("😨Ɣ·®x¯ÍĞ.Ʌ"), "5ù¨ȼ♕☩♘⚁^"));
+
+    // Parts of clusters don't match
+    let family = "👨\u{200d}👩\u{200d}👧";
+    assert!(has_match("👩", family));
+    assert!(!super::has_match(&graphemes("👩"), family));
+    assert!(super::has_match(&graphemes(family), family));
+    assert!(has_match("🇷🇬", "🇫🇷🇬🇧"));
+    assert!(!super::has_match(&graphemes("🇷🇬"), "🇫🇷🇬🇧"));
+    assert!(has_match("e", "cafe\u{301}"));
+    assert!(!super::has_match(&graphemes("e"), "cafe\u{301}"));
+    assert!(super::has_match(&graphemes("e\u{301}"), "cafe\u{301}"));
+    assert!(!super::has_match(&graphemes("É"), "cafe\u{301}"));
+    let options = QueryOptions {
+      graphemes: true,
+      normalization: Normalization::Nfc,
+      ..QueryOptions::default()
+    };
+    assert!(super::has_match(&Query::with_options("É", &options), "cafe\u{301}"));
+  }
+
+  #[test]
+  fn grapheme_locate() {
+    let config = ScoringConfig::default();
+    let candidate = "🇫🇷🇬🇧😨👍🏽Ɣ";
+    let result = super::locate(&graphemes("🇬🇧👍🏽"), candidate, &config);
+    assert_eq!(8, result.match_mask.len());
+    assert_eq!(vec![2, 3, 5, 6], positions(&result));
+    assert_eq!(
+      SCORE_GAP_LEADING + SCORE_GAP_INNER + SCORE_GAP_TRAILING,
+      result.score
+    );
+    assert_eq!(result.score, super::score(&graphemes("🇬🇧👍🏽"), candidate, &config).score);
+
+    // The accent is highlighted with its letter
+    let result = super::locate(&graphemes("ce\u{301}"), "Cafe\u{301}", &config);
+    assert_eq!(vec![0, 3, 4], positions(&result));
+    let result = super::locate(&graphemes("ɅƁ"), "😨Ɣ·®x¯ÍĞ.ɅƁñîƹ♺àwÑ☆ǈ😞´ƙºÑ♫", &config);
+    assert_eq!(vec![9, 10], positions(&result));
+  }
+
+  #[test]
+  fn match_indices() {
+    let candidate = "app/models/ßørder";
+    let result = locate("amør", candidate);
+    assert_eq!(
+      vec![(0, 0), (4, 4), (12, 13), (13, 15)],
+      result.match_indices(candidate).collect::<Vec<_>>()
+    );
+    assert_eq!(vec![0..1, 4..5, 13..16], result.match_ranges(candidate));
+
+    let candidate = "прописная БУКВА";
+    let result = locate("про бук", candidate);
+    assert_eq!(vec![0..6, 18..25], result.match_ranges(candidate));
+    assert_eq!("про", &candidate[0..6]);
+    assert_eq!(" БУК", &candidate[18..25]);
+
+    let result = locate("xyz", candidate);
+    assert_eq!(0, result.match_indices(candidate).count());
+    assert!(result.match_ranges(candidate).is_empty());
+
+    // Whole clusters are covered
+    let candidate = "🇫🇷🇬🇧x";
+    let result = super::locate(&graphemes("🇬🇧"), candidate, &ScoringConfig::default());
+    assert_eq!(vec![8..16], result.match_ranges(candidate));
+  }
+
+  #[test]
+  fn bonus_schemes() {
+    let rust = ScoringConfig {
+      bonus_scheme: Arc::new(RustPathBonuses),
+      ..ScoringConfig::default()
+    };
+    let query = Query::new("c");
+    let default_score = super::score(&query, "std::collections", &ScoringConfig::default()).score;
+    let rust_score = super::score(&query, "std::collections", &rust).score;
+    assert!((rust_score - default_score - SCORE_MATCH_SLASH).abs() < 1e-9);
+    assert_eq!(
+      super::score(&query, "std::collections", &rust).score,
+      super::locate(&query, "std::collections", &rust).score
+    );
+
+    let windows = ScoringConfig {
+      bonus_scheme: Arc::new(WindowsPathBonuses),
+      ..ScoringConfig::default()
+    };
+    let query = Query::new("sm");
+    assert!(
+      super::score(&query, r"src\main.rs", &windows).score
+        > super::score(&query, r"src\main.rs", &ScoringConfig::default()).score
+    );
+    let explanation = super::explain(&query, r"src\main.rs", &windows);
+    assert!(explanation.terms[0].parts.contains(&ScorePart::Match {
+      position: 4,
+      bonus: Bonus::Slash,
+      score: SCORE_MATCH_SLASH,
+    }));
+  }
+
+  #[test]
+  fn path_mode() {
+    let plain = ScoringConfig::default();
+    let path = ScoringConfig {
+      path: Some(PathScoring::default()),
+      ..ScoringConfig::default()
+    };
+    let extra = |query: &str, candidate: &str| {
+      let query = Query::new(query);
+      super::score(&query, candidate, &path).score - super::score(&query, candidate, &plain).score
+    };
+
+    // Matches in the file name are preferred
+    let query = Query::new("mod");
+    assert!(
+      super::score(&query, "models/foo.rs", &plain).score
+        > super::score(&query, "app/foo/mod.rs", &plain).score
+    );
+    assert!(
+      super::score(&query, "models/foo.rs", &path).score
+        < super::score(&query, "app/foo/mod.rs", &path).score
+    );
+    assert!((extra("mod", "app/foo/mod.rs") - 3.0 * SCORE_MATCH_BASENAME).abs() < 1e-9);
+
+    // Matches spread across directories are penalised, either separator
+    assert!((extra("abc", "a/b/c/xyz") - 2.0 * SCORE_GAP_DIRECTORY).abs() < 1e-9);
+    assert!((extra("abc", r"a\b\c\xyz") - 2.0 * SCORE_GAP_DIRECTORY).abs() < 1e-9);
+    assert!((extra("abc", r"a/b\c\xyz") - 2.0 * SCORE_GAP_DIRECTORY).abs() < 1e-9);
+    assert!((extra("main", r"src\main.rs") - 4.0 * SCORE_MATCH_BASENAME).abs() < 1e-9);
+    assert!(
+      super::score(&Query::new("abc"), "abc/a/b/c", &path).score
+        > super::score(&Query::new("abc"), "a/b/c/abc", &plain).score
+    );
+
+    let graphemes = QueryOptions {
+      graphemes: true,
+      ..QueryOptions::default()
+    };
+    let long = format!("{}/src/main.rs", "dir/".repeat(500));
+    let cases = [
+      (Query::new("srmain"), "src/score/main.rs"),
+      (Query::new("äbc"), "ä/x/b/xyz/c.rs"),
+      (Query::with_options("a👍🏽c", &graphemes), "a/👍🏽/c"),
+      (Query::new("dmain"), long.as_str()),
+    ];
+    for &(ref query, candidate) in &cases {
+      let score = super::score(query, candidate, &path).score;
+      assert_eq!(score, super::locate(query, candidate, &path).score, "{}", candidate);
+
+      let explanation = super::explain(query, candidate, &path);
+      assert!((score - explanation.score).abs() < 1e-9, "{}", candidate);
+      let total: Score = explanation.terms[0]
+        .parts
+        .iter()
+        .map(|part| match *part {
+          ScorePart::Match { score, .. }
+          | ScorePart::Gap { score, .. }
+          | ScorePart::Basename { score, .. } => score,
+        })
+        .sum();
+      assert!((score - total).abs() < 1e-9, "{}", candidate);
+    }
+  }
 }
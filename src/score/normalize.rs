@@ -0,0 +1,139 @@
+extern crate unicode_normalization;
+extern crate unicode_segmentation;
+
+use self::unicode_normalization::char::is_combining_mark;
+use self::unicode_normalization::UnicodeNormalization;
+use self::unicode_segmentation::UnicodeSegmentation;
+
+/// Unicode normalization form applied to queries and candidates before
+/// matching
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Normalization {
+  /// Characters are matched as they are
+  #[default]
+  None,
+  /// Canonical composition, so that e.g. "é" matches "e" followed by a
+  /// combining acute accent
+  Nfc,
+  /// Compatibility composition, which additionally matches e.g. "ﬁ" with "fi"
+  Nfkc,
+}
+
+/// Applies a `Normalization` and, optionally, removes diacritics
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Normalizer {
+  form: Normalization,
+  strip_diacritics: bool,
+}
+
+impl Normalizer {
+  pub(crate) fn new(form: Normalization, strip_diacritics: bool) -> Self {
+    Self {
+      form,
+      strip_diacritics,
+    }
+  }
+
+  /// Returns `true` if this changes nothing
+  pub(crate) fn is_identity(&self) -> bool {
+    self.form == Normalization::None && !self.strip_diacritics
+  }
+
+  /// Appends the normalized characters of `text` to `out`
+  pub(crate) fn normalize(&self, text: &str, out: &mut Vec<char>) {
+    match (self.form, self.strip_diacritics) {
+      (Normalization::None, false) => out.extend(text.chars()),
+      (Normalization::Nfc, false) => out.extend(text.nfc()),
+      (Normalization::Nfkc, false) => out.extend(text.nfkc()),
+      (Normalization::Nfkc, true) => {
+        out.extend(text.nfkd().filter(|&c| !is_combining_mark(c)).nfc())
+      }
+      (_, true) => out.extend(text.nfd().filter(|&c| !is_combining_mark(c)).nfc()),
+    }
+  }
+
+  /// Appends the normalized characters of `text` to `out`, normalizing each
+  /// grapheme cluster on its own in the same way as `normalize_candidate`
+  pub(crate) fn normalize_graphemes(&self, text: &str, out: &mut Vec<char>) {
+    for grapheme in text.graphemes(true) {
+      self.normalize(grapheme, out);
+    }
+  }
+
+  /// Normalizes a candidate, keeping track of where each normalized character
+  /// came from
+  ///
+  /// Each grapheme cluster of `candidate` is normalized on its own. Every
+  /// normalized character gets the index of its cluster in `clusters`, and
+  /// `cluster_starts` gets the index of the first original character of each
+  /// cluster, followed by the total number of original characters.
+  pub(crate) fn normalize_candidate(
+    &self,
+    candidate: &str,
+    out: &mut Vec<char>,
+    clusters: &mut Vec<usize>,
+    cluster_starts: &mut Vec<usize>,
+  ) {
+    out.clear();
+    clusters.clear();
+    cluster_starts.clear();
+
+    let mut original_index = 0;
+    for (cluster, grapheme) in candidate.graphemes(true).enumerate() {
+      cluster_starts.push(original_index);
+      original_index += grapheme.chars().count();
+
+      self.normalize(grapheme, out);
+      clusters.resize(out.len(), cluster);
+    }
+    cluster_starts.push(original_index);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn normalized(normalizer: Normalizer, text: &str) -> String {
+    let mut out = Vec::new();
+    normalizer.normalize(text, &mut out);
+    out.into_iter().collect()
+  }
+
+  #[test]
+  fn forms() {
+    let none = Normalizer::default();
+    let nfc = Normalizer::new(Normalization::Nfc, false);
+    let nfkc = Normalizer::new(Normalization::Nfkc, false);
+    let stripped = Normalizer::new(Normalization::None, true);
+    let nfkc_stripped = Normalizer::new(Normalization::Nfkc, true);
+
+    assert!(none.is_identity());
+    assert!(!nfc.is_identity());
+    assert!(!stripped.is_identity());
+
+    assert_eq!("cafe\u{301}", normalized(none, "cafe\u{301}"));
+    assert_eq!("café", normalized(nfc, "cafe\u{301}"));
+    assert_eq!("café", normalized(nfc, "café"));
+    assert_eq!("ﬁle", normalized(nfc, "ﬁle"));
+    assert_eq!("file", normalized(nfkc, "ﬁle"));
+    assert_eq!("cafe", normalized(stripped, "café"));
+    assert_eq!("Muller", normalized(stripped, "Mu\u{308}ller"));
+    assert_eq!("ﬁance", normalized(stripped, "ﬁancé"));
+    assert_eq!("fiance", normalized(nfkc_stripped, "ﬁancé"));
+    assert_eq!("한", normalized(stripped, "\u{1112}\u{1161}\u{11ab}"));
+  }
+
+  #[test]
+  fn candidate_clusters() {
+    let normalizer = Normalizer::new(Normalization::Nfkc, true);
+    let mut out = Vec::new();
+    let mut clusters = Vec::new();
+    let mut cluster_starts = Vec::new();
+    normalizer.normalize_candidate("ﬁ-cafe\u{301}", &mut out, &mut clusters, &mut cluster_starts);
+
+    assert_eq!("fi-cafe".chars().collect::<Vec<_>>(), out);
+    assert_eq!(vec![0, 0, 1, 2, 3, 4, 5], clusters);
+    assert_eq!(vec![0, 1, 2, 3, 4, 5, 7], cluster_starts);
+  }
+}
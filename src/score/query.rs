@@ -0,0 +1,629 @@
+use super::grapheme::{graphemes, GraphemeTable, OTHER_GRAPHEME};
+use super::normalize::{Normalization, Normalizer};
+
+/// How letter case is treated when matching
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaseMatching {
+  /// Upper and lowercase letters match each other
+  #[default]
+  Insensitive,
+  /// Letters only match letters of the same case
+  Sensitive,
+  /// Case sensitive if and only if the query contains an uppercase letter
+  Smart,
+}
+
+/// Options controlling how a query string is turned into a `Query`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueryOptions {
+  pub case: CaseMatching,
+  /// Parse the query using the extended search syntax
+  ///
+  /// Terms are separated by spaces and must all match. Each term is a fuzzy
+  /// match, unless it is written as:
+  /// + `'exact` to match an exact substring
+  /// + `^prefix` to match the start of a candidate
+  /// + `suffix$` to match the end of a candidate
+  /// + `^whole$` to match an entire candidate
+  /// + `!term` to match candidates that don't contain the exact `term`
+  ///   (which may also be used with `^` and `$`)
+  ///
+  /// A `|` between terms matches either of them.
+  pub extended: bool,
+  /// Normalization applied to both the query and candidates
+  pub normalization: Normalization,
+  /// Remove diacritics (combining marks, after decomposition) from both the
+  /// query and candidates, so that e.g. "e" matches "é"
+  pub strip_diacritics: bool,
+  /// Match whole extended grapheme clusters rather than single characters,
+  /// so that e.g. an emoji flag or a letter with a combining accent is never
+  /// partially matched
+  pub graphemes: bool,
+}
+
+/// A query prepared for matching against many candidates
+///
+/// The case-folding of the query is done once, here, so that only the
+/// candidate side needs folding while matching.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+  text: String,
+  ascii: bool,
+  case_sensitive: bool,
+  normalizer: Normalizer,
+  // The clusters of the query, only when matching grapheme clusters
+  graphemes: Option<GraphemeTable>,
+  // Groups of alternative terms, a candidate must match a term from every
+  // group. A query without the extended syntax is a single fuzzy term.
+  groups: Vec<Vec<Term>>,
+}
+
+/// A single term of a query
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Term {
+  kind: TermKind,
+  inverse: bool,
+  folded: Vec<char>,
+  // Only filled for ASCII queries
+  folded_bytes: Vec<u8>,
+  // Only filled when matching grapheme clusters
+  grapheme_ids: Vec<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TermKind {
+  Fuzzy,
+  Exact,
+  Prefix,
+  Suffix,
+  Equal,
+}
+
+impl Query {
+  /// Prepares a case-insensitive query
+  pub fn new(query: &str) -> Self {
+    Self::with_options(query, &QueryOptions::default())
+  }
+
+  pub fn with_case(query: &str, case: CaseMatching) -> Self {
+    Self::with_options(
+      query,
+      &QueryOptions {
+        case,
+        ..QueryOptions::default()
+      },
+    )
+  }
+
+  pub fn with_options(query: &str, options: &QueryOptions) -> Self {
+    let case_sensitive = match options.case {
+      CaseMatching::Insensitive => false,
+      CaseMatching::Sensitive => true,
+      CaseMatching::Smart => query.chars().any(char::is_uppercase),
+    };
+
+    let normalizer = Normalizer::new(options.normalization, options.strip_diacritics);
+    let ascii = if normalizer.is_identity() {
+      query.is_ascii()
+    } else {
+      let mut normalized = Vec::new();
+      normalizer.normalize(query, &mut normalized);
+      normalized.iter().all(char::is_ascii)
+    };
+    // "\r\n" is the only ASCII grapheme cluster of more than one character
+    let ascii = ascii && !(options.graphemes && query.contains('\r'));
+
+    let mut out = Self {
+      text: query.to_string(),
+      ascii,
+      case_sensitive,
+      normalizer,
+      graphemes: if options.graphemes {
+        Some(GraphemeTable::default())
+      } else {
+        None
+      },
+      groups: Vec::new(),
+    };
+
+    if options.extended {
+      out.groups = parse_extended(query, &mut out);
+    } else if let Some(term) = out.term(TermKind::Fuzzy, false, query) {
+      out.groups = vec![vec![term]];
+    }
+
+    out
+  }
+
+  /// The original query string
+  pub fn as_str(&self) -> &str {
+    &self.text
+  }
+
+  /// Returns `true` if the query has nothing to match
+  pub fn is_empty(&self) -> bool {
+    self.groups.is_empty()
+  }
+
+  /// Returns `true` if the (normalized) query contains only ASCII characters
+  pub fn is_ascii(&self) -> bool {
+    self.ascii
+  }
+
+  /// Returns `true` if the query only matches letters of the same case,
+  /// having resolved `CaseMatching::Smart`
+  pub fn is_case_sensitive(&self) -> bool {
+    self.case_sensitive
+  }
+
+  /// Returns `true` if the query matches grapheme clusters rather than
+  /// characters
+  pub fn matches_graphemes(&self) -> bool {
+    self.graphemes.is_some()
+  }
+
+  /// Returns `true` if `candidate` can be matched byte by byte
+  pub(crate) fn is_ascii_match(&self, candidate: &str) -> bool {
+    self.ascii && candidate.is_ascii() && !(self.matches_graphemes() && candidate.contains('\r'))
+  }
+
+  /// The id of a normalized and folded candidate cluster
+  pub(crate) fn grapheme_id(&self, cluster: &str) -> u32 {
+    self
+      .graphemes
+      .as_ref()
+      .map_or(OTHER_GRAPHEME, |table| table.id(cluster))
+  }
+
+  /// Folds a candidate character the same way as the query
+  pub(crate) fn fold(&self, character: char) -> char {
+    if self.case_sensitive {
+      character
+    } else {
+      fold_case(character)
+    }
+  }
+
+  /// Folds an ASCII candidate byte the same way as the query
+  pub(crate) fn fold_byte(&self, byte: u8) -> u8 {
+    if self.case_sensitive {
+      byte
+    } else {
+      byte.to_ascii_lowercase()
+    }
+  }
+
+  pub(crate) fn normalizer(&self) -> Normalizer {
+    self.normalizer
+  }
+
+  pub(crate) fn groups(&self) -> &[Vec<Term>] {
+    &self.groups
+  }
+
+  /// The only term of the query, if it is a single fuzzy term
+  pub(crate) fn fuzzy_term(&self) -> Option<&Term> {
+    match self.groups.as_slice() {
+      [group] => match group.as_slice() {
+        [term] if term.kind == TermKind::Fuzzy => Some(term),
+        _ => None,
+      },
+      _ => None,
+    }
+  }
+
+  // Makes a term, unless normalization leaves nothing to match
+  fn term(&mut self, kind: TermKind, inverse: bool, text: &str) -> Option<Term> {
+    let mut folded = Vec::with_capacity(text.len());
+    self.normalizer.normalize(text, &mut folded);
+    if folded.is_empty() {
+      return None;
+    }
+    for c in &mut folded {
+      *c = self.fold(*c);
+    }
+
+    let folded_bytes = if self.ascii {
+      folded.iter().map(|&c| c as u8).collect()
+    } else {
+      Vec::new()
+    };
+
+    let mut grapheme_ids = Vec::new();
+    if let Some(ref mut table) = self.graphemes {
+      let mut normalized = Vec::new();
+      for grapheme in graphemes(text) {
+        normalized.clear();
+        self.normalizer.normalize(grapheme, &mut normalized);
+        if !normalized.is_empty() {
+          let case_sensitive = self.case_sensitive;
+          let cluster: String = normalized
+            .iter()
+            .map(|&c| if case_sensitive { c } else { fold_case(c) })
+            .collect();
+          grapheme_ids.push(table.intern(&cluster));
+        }
+      }
+    }
+
+    Some(Term {
+      kind,
+      inverse,
+      folded,
+      folded_bytes,
+      grapheme_ids,
+    })
+  }
+}
+
+impl<'a> From<&'a str> for Query {
+  fn from(query: &'a str) -> Self {
+    Self::new(query)
+  }
+}
+
+impl Term {
+  pub(crate) fn kind(&self) -> TermKind {
+    self.kind
+  }
+
+  pub(crate) fn is_inverse(&self) -> bool {
+    self.inverse
+  }
+
+  /// Number of characters (or grapheme clusters) in the term
+  pub(crate) fn len(&self) -> usize {
+    if self.grapheme_ids.is_empty() {
+      self.folded.len()
+    } else {
+      self.grapheme_ids.len()
+    }
+  }
+
+  pub(crate) fn folded(&self) -> &[char] {
+    &self.folded
+  }
+
+  /// The folded term as bytes, only meaningful if the query `is_ascii`
+  pub(crate) fn folded_bytes(&self) -> &[u8] {
+    &self.folded_bytes
+  }
+
+  /// The ids of the grapheme clusters of the term, only filled when matching
+  /// grapheme clusters
+  pub(crate) fn grapheme_ids(&self) -> &[u32] {
+    &self.grapheme_ids
+  }
+
+  /// Returns `true` if the term matches an already-folded candidate
+  pub(crate) fn is_match<U: MatchUnit>(&self, candidate: &[U]) -> bool {
+    let needle = U::needle(self);
+    let found = match self.kind {
+      TermKind::Fuzzy => {
+        let mut cand_iter = candidate.iter();
+        needle.iter().all(|q| cand_iter.any(|c| c == q))
+      }
+      TermKind::Exact => candidate.windows(needle.len()).any(|w| w == needle),
+      TermKind::Prefix => candidate.starts_with(needle),
+      TermKind::Suffix => candidate.ends_with(needle),
+      TermKind::Equal => candidate == needle,
+    };
+    found != self.inverse
+  }
+
+  /// Collects the positions an exact (non-fuzzy) term could start at in an
+  /// already-folded candidate
+  pub(crate) fn exact_starts<U: MatchUnit>(&self, candidate: &[U], out: &mut Vec<usize>) {
+    let needle = U::needle(self);
+    out.clear();
+    match self.kind {
+      TermKind::Fuzzy => {}
+      TermKind::Exact => out.extend(
+        candidate
+          .windows(needle.len())
+          .enumerate()
+          .filter(|&(_, w)| w == needle)
+          .map(|(start, _)| start),
+      ),
+      TermKind::Prefix | TermKind::Equal => {
+        if candidate.starts_with(needle) && (self.kind == TermKind::Prefix || candidate == needle) {
+          out.push(0);
+        }
+      }
+      TermKind::Suffix => {
+        if candidate.ends_with(needle) {
+          out.push(candidate.len() - needle.len());
+        }
+      }
+    }
+  }
+}
+
+/// A unit of a folded candidate that terms can be matched against
+pub(crate) trait MatchUnit: Copy + PartialEq {
+  fn needle(term: &Term) -> &[Self];
+}
+
+impl MatchUnit for char {
+  fn needle(term: &Term) -> &[Self] {
+    term.folded()
+  }
+}
+
+impl MatchUnit for u8 {
+  fn needle(term: &Term) -> &[Self] {
+    term.folded_bytes()
+  }
+}
+
+impl MatchUnit for u32 {
+  fn needle(term: &Term) -> &[Self] {
+    term.grapheme_ids()
+  }
+}
+
+fn parse_extended(query: &str, parsed: &mut Query) -> Vec<Vec<Term>> {
+  let mut groups: Vec<Vec<Term>> = Vec::new();
+  let mut alternative = false;
+
+  for token in query.split(' ').filter(|token| !token.is_empty()) {
+    if token == "|" {
+      alternative = !groups.is_empty();
+      continue;
+    }
+
+    let (kind, inverse, text) = parse_term(token);
+    let term = match parsed.term(kind, inverse, text) {
+      Some(term) => term,
+      None => continue,
+    };
+    match groups.last_mut() {
+      Some(group) if alternative => group.push(term),
+      _ => groups.push(vec![term]),
+    }
+    alternative = false;
+  }
+
+  groups
+}
+
+fn parse_term(token: &str) -> (TermKind, bool, &str) {
+  let (inverse, text) = match strip_prefix(token, '!') {
+    Some(rest) => (true, rest),
+    None => (false, token),
+  };
+
+  if let Some(rest) = strip_prefix(text, '\'') {
+    return (TermKind::Exact, inverse, rest);
+  }
+
+  let (prefix, text) = match strip_prefix(text, '^') {
+    Some(rest) => (true, rest),
+    None => (false, text),
+  };
+  let (suffix, text) = match strip_suffix(text, '$') {
+    Some(rest) => (true, rest),
+    None => (false, text),
+  };
+
+  let kind = match (prefix, suffix) {
+    (true, true) => TermKind::Equal,
+    (true, false) => TermKind::Prefix,
+    (false, true) => TermKind::Suffix,
+    (false, false) if inverse => TermKind::Exact,
+    (false, false) => TermKind::Fuzzy,
+  };
+  (kind, inverse, text)
+}
+
+// Strips an operator from the start of a token, so long as something is left
+fn strip_prefix(token: &str, operator: char) -> Option<&str> {
+  if token.len() > operator.len_utf8() && token.starts_with(operator) {
+    Some(&token[operator.len_utf8()..])
+  } else {
+    None
+  }
+}
+
+// Strips an operator from the end of a token, so long as something is left
+fn strip_suffix(token: &str, operator: char) -> Option<&str> {
+  if token.len() > operator.len_utf8() && token.ends_with(operator) {
+    Some(&token[..token.len() - operator.len_utf8()])
+  } else {
+    None
+  }
+}
+
+/// Folds a character to its lowercase form, if that is a single character
+///
+/// Characters whose lowercase form is several characters only ever match
+/// themselves, so they are left as they are.
+pub(crate) fn fold_case(character: char) -> char {
+  let mut lower = character.to_lowercase();
+  match (lower.next(), lower.next()) {
+    (Some(folded), None) => folded,
+    _ => character,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn extended(query: &str) -> Query {
+    Query::with_options(
+      query,
+      &QueryOptions {
+        extended: true,
+        ..QueryOptions::default()
+      },
+    )
+  }
+
+  fn kinds(query: &Query) -> Vec<Vec<(TermKind, bool, String)>> {
+    query
+      .groups()
+      .iter()
+      .map(|group| {
+        group
+          .iter()
+          .map(|t| (t.kind(), t.is_inverse(), t.folded().iter().collect()))
+          .collect()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn folding() {
+    let query = Query::new("QuEry ПРОПИСНАЯ");
+    assert_eq!("QuEry ПРОПИСНАЯ", query.as_str());
+    let term = query.fuzzy_term().unwrap();
+    assert_eq!("query прописная".chars().collect::<Vec<_>>(), term.folded());
+    assert_eq!(15, term.len());
+    assert!(!query.is_ascii());
+  }
+
+  #[test]
+  fn ascii() {
+    assert!(Query::new("").is_ascii());
+    assert!(Query::new("156aufsdn926f9=sdk/~']").is_ascii());
+    assert!(!Query::new("ñ").is_ascii());
+    assert!(Query::new("").is_empty());
+    assert_eq!(b"query", Query::new("QuEry").fuzzy_term().unwrap().folded_bytes());
+  }
+
+  #[test]
+  fn case_matching() {
+    assert!(!Query::new("Vec").is_case_sensitive());
+    assert!(Query::with_case("vec", CaseMatching::Sensitive).is_case_sensitive());
+    assert!(!Query::with_case("Vec", CaseMatching::Insensitive).is_case_sensitive());
+    assert!(Query::with_case("Vec", CaseMatching::Smart).is_case_sensitive());
+    assert!(!Query::with_case("vec", CaseMatching::Smart).is_case_sensitive());
+    assert!(Query::with_case("вЕк", CaseMatching::Smart).is_case_sensitive());
+
+    let query = Query::with_case("Vec", CaseMatching::Sensitive);
+    let term = query.fuzzy_term().unwrap();
+    assert_eq!(vec!['V', 'e', 'c'], term.folded());
+    assert_eq!(b"Vec", term.folded_bytes());
+    assert_eq!('A', query.fold('A'));
+    assert_eq!('a', Query::new("Vec").fold('A'));
+  }
+
+  #[test]
+  fn multi_char_lowercase() {
+    assert_eq!('İ', fold_case('İ'));
+    assert_eq!('a', fold_case('A'));
+    assert_eq!('😨', fold_case('😨'));
+  }
+
+  #[test]
+  fn plain_spaces() {
+    let query = Query::new("a b");
+    assert_eq!(3, query.fuzzy_term().unwrap().len());
+    assert!(!Query::new(" ").is_empty());
+  }
+
+  #[test]
+  fn extended_syntax() {
+    use self::TermKind::*;
+
+    let t = |kind, inverse, text: &str| (kind, inverse, text.to_string());
+
+    assert!(extended("").is_empty());
+    assert!(extended("   ").is_empty());
+    assert_eq!(vec![vec![t(Fuzzy, false, "abc")]], kinds(&extended("abc")));
+    assert!(extended("abc").fuzzy_term().is_some());
+    assert_eq!(
+      vec![
+        vec![t(Fuzzy, false, "foo")],
+        vec![t(Exact, false, "bar")],
+        vec![t(Prefix, false, "src")],
+        vec![t(Suffix, false, ".rs")],
+        vec![t(Equal, false, "all")],
+      ],
+      kinds(&extended("foo  'bar ^src .rs$ ^all$"))
+    );
+    assert_eq!(
+      vec![
+        vec![t(Exact, true, "test")],
+        vec![t(Prefix, true, "x")],
+        vec![t(Suffix, true, ".md")],
+        vec![t(Exact, true, "y")],
+      ],
+      kinds(&extended("!test !^x !.md$ !'y"))
+    );
+    assert_eq!(
+      vec![
+        vec![t(Prefix, false, "core")],
+        vec![t(Suffix, false, "go"), t(Suffix, false, "rb"), t(Fuzzy, false, "py")],
+      ],
+      kinds(&extended("^core go$ | rb$ | py"))
+    );
+    assert!(extended("a | b").fuzzy_term().is_none());
+  }
+
+  #[test]
+  fn extended_lone_operators() {
+    use self::TermKind::*;
+
+    let t = |kind, inverse, text: &str| (kind, inverse, text.to_string());
+    assert_eq!(
+      vec![
+        vec![t(Fuzzy, false, "!")],
+        vec![t(Fuzzy, false, "'")],
+        vec![t(Fuzzy, false, "^")],
+        vec![t(Fuzzy, false, "$")],
+        vec![t(Fuzzy, false, "a")],
+      ],
+      kinds(&extended("| ! ' ^ $ a |"))
+    );
+  }
+
+  #[test]
+  fn normalization() {
+    let options = QueryOptions {
+      normalization: Normalization::Nfc,
+      ..QueryOptions::default()
+    };
+    let query = Query::with_options("Cafe\u{301}", &options);
+    assert_eq!("Cafe\u{301}", query.as_str());
+    assert_eq!(vec!['c', 'a', 'f', 'é'], query.fuzzy_term().unwrap().folded());
+    assert!(!query.is_ascii());
+
+    let options = QueryOptions {
+      strip_diacritics: true,
+      ..options
+    };
+    let query = Query::with_options("Café", &options);
+    assert_eq!(b"cafe", query.fuzzy_term().unwrap().folded_bytes());
+    assert!(query.is_ascii());
+
+    assert!(Query::with_options("\u{301}", &options).is_empty());
+    let options = QueryOptions {
+      extended: true,
+      ..options
+    };
+    assert_eq!(1, Query::with_options("\u{301} é", &options).groups().len());
+  }
+
+  #[test]
+  fn term_matching() {
+    let query = extended("Foo 'oba ^fo ar$ !baz");
+    let candidate: Vec<char> = "foobar".chars().collect();
+    for group in query.groups() {
+      assert!(group[0].is_match(&candidate));
+    }
+    let candidate: Vec<char> = "foobarbaz".chars().collect();
+    assert!(!query.groups()[4][0].is_match(&candidate));
+
+    let mut starts = Vec::new();
+    extended("'a").groups()[0][0].exact_starts(b"banana", &mut starts);
+    assert_eq!(vec![1, 3, 5], starts);
+    extended("na$").groups()[0][0].exact_starts(b"banana", &mut starts);
+    assert_eq!(vec![4], starts);
+    extended("^na").groups()[0][0].exact_starts(b"banana", &mut starts);
+    assert!(starts.is_empty());
+    extended("^ban$").groups()[0][0].exact_starts(b"banana", &mut starts);
+    assert!(starts.is_empty());
+  }
+}
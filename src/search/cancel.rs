@@ -0,0 +1,78 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How many candidates a search thread scores between checks for
+/// cancellation
+pub(crate) const CANCEL_CHECK_INTERVAL: usize = 1024;
+
+/// A flag for stopping searches early, which can be cloned and shared with
+/// the threads that cancel them
+///
+/// Searches check the token every `CANCEL_CHECK_INTERVAL` candidates, so they
+/// stop soon after it is cancelled, but not immediately.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+  cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Cancels every search using this token (or one of its clones)
+  pub fn cancel(&self) {
+    self.cancelled.store(true, Ordering::Relaxed);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::Relaxed)
+  }
+}
+
+/// The error of a search that was cancelled before it finished
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "search cancelled")
+  }
+}
+
+impl Error for Cancelled {}
+
+// Whether a search with an optional token has been cancelled
+pub(crate) fn is_cancelled(cancel: Option<&CancellationToken>) -> bool {
+  cancel.is_some_and(CancellationToken::is_cancelled)
+}
+
+// The results of a search, unless it was cancelled before it finished
+pub(crate) fn unless_cancelled<T>(results: T, cancel: &CancellationToken) -> Result<T, Cancelled> {
+  if cancel.is_cancelled() {
+    Err(Cancelled)
+  } else {
+    Ok(results)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::thread;
+
+  #[test]
+  fn shared_between_threads() {
+    let token = CancellationToken::new();
+    assert!(!token.is_cancelled());
+    assert!(!is_cancelled(Some(&token)));
+    assert!(!is_cancelled(None));
+
+    let clone = token.clone();
+    thread::spawn(move || clone.cancel()).join().unwrap();
+    assert!(token.is_cancelled());
+    assert!(is_cancelled(Some(&token)));
+  }
+}
@@ -0,0 +1,78 @@
+use std::iter;
+use std::thread;
+
+use score::config::ScoringConfig;
+use score::{Matcher, Query};
+
+use super::itertools::kmerge;
+use super::{
+  as_str, by_key, calculate_parallelism, ceil_div, search_worker, BestFirst, Hooks, ScoreResults,
+};
+
+/// As `search_score`, but splitting the candidates into one equal slice for
+/// each of `parallelism` threads started for the search, as searches did
+/// before they stole chunks of candidates on a pool
+///
+/// Only for benchmarking searches against.
+#[doc(hidden)]
+pub fn search_score_equal_split<S: AsRef<str> + Sync>(
+  query: &Query,
+  candidates: &[S],
+  parallelism: usize,
+  config: &ScoringConfig,
+) -> ScoreResults {
+  let search = by_key(query, &as_str, Matcher::score_indexed);
+  let parallelism = calculate_parallelism(candidates.len(), parallelism, query.is_empty());
+  let per_thread_count = ceil_div(candidates.len(), parallelism);
+
+  let results: Vec<_> = thread::scope(|scope| {
+    let threads: Vec<_> = (0..candidates.len())
+      .step_by(per_thread_count.max(1))
+      .map(|start| {
+        let share = start..(start + per_thread_count).min(candidates.len());
+        let search = &search;
+        scope.spawn(move || {
+          let matcher = Matcher::new(query, config);
+          search_worker(
+            0,
+            candidates,
+            iter::once(share),
+            matcher,
+            usize::MAX,
+            search,
+            &Hooks::none(),
+          )
+        })
+      })
+      .collect();
+    threads
+      .into_iter()
+      .map(|thread| thread.join().unwrap())
+      .collect()
+  });
+  kmerge(results.into_iter().map(BestFirst)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use search::search_score;
+
+  #[test]
+  fn same_as_searching() {
+    let candidates: Vec<String> = (0..5_000).map(|i| format!("{}/{}", i % 17, i)).collect();
+    let config = ScoringConfig::default();
+    for parallelism in 1..6 {
+      for query in &["", "1", "1/2", "nothing"] {
+        let query = Query::new(query);
+        let indices = |results: ScoreResults| -> Vec<usize> {
+          results.iter().map(|r| r.candidate_index).collect()
+        };
+        assert_eq!(
+          indices(search_score(&query, &candidates, parallelism, &config)),
+          indices(search_score_equal_split(&query, &candidates, parallelism, &config))
+        );
+      }
+    }
+  }
+}
@@ -0,0 +1,452 @@
+use std::cmp::Ordering;
+use std::ops::Deref;
+
+use score::config::{ScoringConfig, SCORE_MAX, SCORE_MIN};
+use score::{has_match, LocateResult, Matcher, Query, Score};
+
+use super::{search_internal, Hooks, ItemResult};
+
+/// A searchable text field of structured items, with the text given by `key`
+///
+/// The fields searched together share a key type, such as `FieldKey<C>` for
+/// functions and closures that capture nothing, or
+/// `Box<dyn Fn(&C) -> &str + Sync>` for closures that do.
+pub struct Field<K> {
+  /// How much matches in this field count, relative to a weight of 1
+  ///
+  /// The logarithm of the weight is added to the scores of matches in this
+  /// field, so a heavier field scores higher whether its scores are positive
+  /// or negative. Weights must be positive.
+  pub weight: Score,
+  /// Gives the text of this field of an item
+  pub key: K,
+}
+
+/// Key of a field given by a function, or a closure that captures nothing
+pub type FieldKey<C> = fn(&C) -> &str;
+
+/// How the scores of the fields of an item are combined into its score
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldScoring {
+  /// The best weighted score of any field
+  Best,
+  /// The sum of the weighted scores of every field that matches
+  ///
+  /// A field that matches exactly makes the sum `SCORE_MAX`, while fields that
+  /// score `SCORE_MIN` (as they all do for an empty query) only count if none
+  /// of the others match.
+  Sum,
+}
+
+/// Result of searching the fields of an item
+///
+/// This dereferences to the result for the best matching field, so
+/// `result.match_mask` is where the query matches that field.
+#[derive(Debug)]
+pub struct FieldResult {
+  /// Index of the best matching field, among the searched fields
+  pub field: usize,
+  /// The result for the best matching field, with the score of the item
+  pub result: LocateResult,
+}
+
+impl Deref for FieldResult {
+  type Target = LocateResult;
+
+  fn deref(&self) -> &LocateResult {
+    &self.result
+  }
+}
+
+impl Ord for FieldResult {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self.result.cmp(&other.result)
+  }
+}
+
+impl PartialOrd for FieldResult {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl PartialEq for FieldResult {
+  fn eq(&self, other: &Self) -> bool {
+    self.result == other.result
+  }
+}
+
+impl Eq for FieldResult {}
+
+/// Search among a collection of items by several of their fields, returning
+/// an ordered collection of results (highest score first) along with the
+/// items they are for
+///
+/// An item matches if any of its `fields` matches, and its score combines the
+/// weighted scores of its fields by `scoring`.
+///
+/// # Panics
+///
+/// Panics if the weight of any field isn't positive.
+pub fn search_fields<'a, C, K>(
+  query: &Query,
+  items: &'a [C],
+  fields: &[Field<K>],
+  scoring: FieldScoring,
+  parallelism: usize,
+  config: &ScoringConfig,
+) -> Vec<ItemResult<'a, C, FieldResult>>
+where
+  C: Sync,
+  K: Fn(&C) -> &str + Sync,
+{
+  for field in fields {
+    assert!(
+      field.weight > 0.0,
+      "field weights must be positive, not {}",
+      field.weight
+    );
+  }
+
+  let search = |matcher: &mut Matcher, item: &C, index: usize| {
+    let mut best: Option<(Score, FieldResult)> = None;
+    let mut total = SCORE_MIN;
+    for (field, &Field { weight, ref key }) in fields.iter().enumerate() {
+      let text = key(item);
+      if !has_match(query, text) {
+        continue;
+      }
+
+      let result = matcher.locate_indexed(text, index);
+      let score = weigh(result.score, weight);
+      total = add(total, score);
+      if best.as_ref().is_none_or(|&(best, _)| score > best) {
+        best = Some((score, FieldResult { field, result }));
+      }
+    }
+
+    best.map(|(score, mut found)| {
+      found.result.score = match scoring {
+        FieldScoring::Best => score,
+        FieldScoring::Sum => total,
+      };
+      found
+    })
+  };
+
+  search_internal(query, items, usize::MAX, parallelism, config, &search, &Hooks::none())
+    .map(|result| ItemResult {
+      item: &items[result.candidate_index],
+      result,
+    })
+    .collect()
+}
+
+// Fuzzy scores are mostly negative, so the weight is added (as its logarithm)
+// rather than multiplied, which would push heavier fields further down
+fn weigh(score: Score, weight: Score) -> Score {
+  if score == SCORE_MAX || score == SCORE_MIN {
+    score
+  } else {
+    score + weight.ln()
+  }
+}
+
+// Adds the score of a field to the sum of the others, where `SCORE_MIN` adds
+// nothing, rather than making `SCORE_MAX + SCORE_MIN` NaN
+fn add(total: Score, score: Score) -> Score {
+  if total == SCORE_MIN {
+    score
+  } else if score == SCORE_MIN {
+    total
+  } else {
+    total + score
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use score::QueryOptions;
+
+  struct Record {
+    name: &'static str,
+    path: &'static str,
+    description: &'static str,
+  }
+
+  const RECORDS: &[Record] = &[
+    Record {
+      name: "parser",
+      path: "src/parse.rs",
+      description: "Reads the query syntax",
+    },
+    Record {
+      name: "matcher",
+      path: "src/score/matcher.rs",
+      description: "Scores a parsed query",
+    },
+    Record {
+      name: "search",
+      path: "src/search/mod.rs",
+      description: "Runs the matcher over many candidates",
+    },
+  ];
+
+  fn fields(name: Score, path: Score, description: Score) -> Vec<Field<FieldKey<Record>>> {
+    vec![
+      Field {
+        weight: name,
+        key: |r| r.name,
+      },
+      Field {
+        weight: path,
+        key: |r| r.path,
+      },
+      Field {
+        weight: description,
+        key: |r| r.description,
+      },
+    ]
+  }
+
+  fn search(
+    query: &str,
+    fields: &[Field<FieldKey<Record>>],
+    scoring: FieldScoring,
+  ) -> Vec<(&'static str, usize)> {
+    let config = ScoringConfig::default();
+    let results = search_fields(&Query::new(query), RECORDS, fields, scoring, 1, &config);
+    for parallelism in 2..5 {
+      let parallel = search_fields(
+        &Query::new(query),
+        RECORDS,
+        fields,
+        scoring,
+        parallelism,
+        &config,
+      );
+      assert_eq!(
+        results
+          .iter()
+          .map(|r| r.candidate_index)
+          .collect::<Vec<_>>(),
+        parallel
+          .iter()
+          .map(|r| r.candidate_index)
+          .collect::<Vec<_>>()
+      );
+    }
+    results.iter().map(|r| (r.item.name, r.field)).collect()
+  }
+
+  #[test]
+  fn best_field() {
+    let even = fields(1.0, 1.0, 1.0);
+    assert_eq!(
+      vec![("matcher", 0), ("search", 2)],
+      search("matcher", &even, FieldScoring::Best)
+    );
+    assert_eq!(
+      vec![("parser", 2)],
+      search("syntax", &even, FieldScoring::Best)
+    );
+    assert!(search("nothing", &even, FieldScoring::Best).is_empty());
+
+    // The description of "search" mentions the matcher
+    let config = ScoringConfig::default();
+    let results = search_fields(
+      &Query::new("matcher"),
+      RECORDS,
+      &even,
+      FieldScoring::Best,
+      1,
+      &config,
+    );
+    assert_eq!(
+      vec![9..16],
+      results[1].match_ranges(results[1].item.description)
+    );
+  }
+
+  #[test]
+  fn weighted_fields() {
+    let query = Query::new("scre");
+    let config = ScoringConfig::default();
+    let score = |field: &str| ::score::score(&query, field, &config).score;
+
+    let results = search_fields(
+      &query,
+      RECORDS,
+      &fields(2.0, 1.0, 0.5),
+      FieldScoring::Best,
+      1,
+      &config,
+    );
+    assert_eq!("matcher", results[0].item.name);
+    assert_eq!(1, results[0].field);
+    assert_eq!(score("src/score/matcher.rs"), results[0].score);
+
+    let results = search_fields(
+      &query,
+      RECORDS,
+      &fields(2.0, 1.0, 0.5),
+      FieldScoring::Sum,
+      1,
+      &config,
+    );
+    let matcher = results.iter().find(|r| r.item.name == "matcher").unwrap();
+    let expected = score("src/score/matcher.rs") + score("Scores a parsed query") + 0.5f64.ln();
+    assert!((expected - matcher.score).abs() < 1e-9);
+
+    // An exact match outranks everything, whatever its weight
+    let results = search_fields(
+      &Query::new("search"),
+      RECORDS,
+      &fields(0.1, 5.0, 5.0),
+      FieldScoring::Best,
+      1,
+      &config,
+    );
+    assert_eq!(("search", 0), (results[0].item.name, results[0].field));
+
+    let results = search_fields(
+      &Query::new(""),
+      RECORDS,
+      &fields(1.0, 1.0, 1.0),
+      FieldScoring::Sum,
+      1,
+      &config,
+    );
+    assert_eq!(3, results.len());
+    assert!(results.iter().all(|r| r.score == SCORE_MIN));
+  }
+
+  #[test]
+  fn heavier_fields_outrank_lighter() {
+    struct Doc {
+      name: &'static str,
+      description: &'static str,
+    }
+    let docs = [
+      Doc {
+        name: "nothing",
+        description: "xaxxbxxcx",
+      },
+      Doc {
+        name: "xaxxbxxcx",
+        description: "nothing",
+      },
+    ];
+    let fields: [Field<FieldKey<Doc>>; 2] = [
+      Field {
+        weight: 10.0,
+        key: |d| d.name,
+      },
+      Field {
+        weight: 1.0,
+        key: |d| d.description,
+      },
+    ];
+    let config = ScoringConfig::default();
+    let query = Query::new("abc");
+    assert!(::score::score(&query, "xaxxbxxcx", &config).score < 0.0);
+
+    for &scoring in &[FieldScoring::Best, FieldScoring::Sum] {
+      let results = search_fields(&query, &docs, &fields, scoring, 1, &config);
+      assert_eq!(
+        vec![("xaxxbxxcx", 0), ("nothing", 1)],
+        results
+          .iter()
+          .map(|r| (r.item.name, r.field))
+          .collect::<Vec<_>>()
+      );
+      assert!(results.iter().all(|r| !r.score.is_nan()));
+    }
+  }
+
+  #[test]
+  fn sum_of_special_scores() {
+    struct Doc {
+      name: &'static str,
+      description: &'static str,
+    }
+    let docs = [
+      Doc {
+        name: "abc",
+        description: "x",
+      },
+      Doc {
+        name: "xaxbxc",
+        description: "x",
+      },
+    ];
+    let fields: [Field<FieldKey<Doc>>; 2] = [
+      Field {
+        weight: 1.0,
+        key: |d| d.name,
+      },
+      Field {
+        weight: 1.0,
+        key: |d| d.description,
+      },
+    ];
+    let config = ScoringConfig::default();
+    let extended = QueryOptions {
+      extended: true,
+      ..QueryOptions::default()
+    };
+    // Matching only by the inverse term scores `SCORE_MIN`
+    let query = Query::with_options("abc | !foo", &extended);
+    assert_eq!(SCORE_MAX, ::score::score(&query, "abc", &config).score);
+    assert_eq!(SCORE_MIN, ::score::score(&query, "x", &config).score);
+
+    let results = search_fields(&query, &docs, &fields, FieldScoring::Sum, 1, &config);
+    assert_eq!(SCORE_MAX, results[0].score);
+    assert_eq!(
+      ::score::score(&query, "xaxbxc", &config).score,
+      results[1].score
+    );
+  }
+
+  #[test]
+  fn capturing_keys() {
+    let prefix = "src/".len();
+    type Key = Box<dyn Fn(&Record) -> &str + Sync>;
+    let fields: Vec<Field<Key>> = vec![
+      Field {
+        weight: 1.0,
+        key: Box::new(move |r: &Record| &r.path[prefix..]),
+      },
+      Field {
+        weight: 1.0,
+        key: Box::new(|r: &Record| r.name),
+      },
+    ];
+    let config = ScoringConfig::default();
+    let results = search_fields(
+      &Query::new("search/mod.rs"),
+      RECORDS,
+      &fields,
+      FieldScoring::Best,
+      1,
+      &config,
+    );
+    assert_eq!(1, results.len());
+    assert_eq!(SCORE_MAX, results[0].score);
+  }
+
+  #[test]
+  #[should_panic(expected = "field weights must be positive")]
+  fn zero_weight() {
+    let config = ScoringConfig::default();
+    search_fields(
+      &Query::new(""),
+      RECORDS,
+      &fields(1.0, 0.0, 1.0),
+      FieldScoring::Best,
+      1,
+      &config,
+    );
+  }
+}
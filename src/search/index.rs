@@ -0,0 +1,330 @@
+use score::config::ScoringConfig;
+use score::{Matcher, PreparedCandidate, Query};
+
+use super::cancel::{is_cancelled, CANCEL_CHECK_INTERVAL};
+use super::{search_internal, CancellationToken, Hooks, LocateResults, ScoreResults};
+
+// Scores or locates a candidate from its cached parts
+pub(super) type CachedFn<T> = fn(&mut Matcher, &str, &PreparedCandidate, usize) -> T;
+
+/// A collection of candidates to search many times, with everything about
+/// each candidate that doesn't depend on the query worked out once
+///
+/// The candidates are scored with the `ScoringConfig` the index is made with.
+/// Candidates are matched against their cached case folded characters, and
+/// normalized once for the first normalizing query. Results give the position
+/// of their candidate in the index, which changes when an earlier candidate
+/// is removed.
+#[derive(Clone, Debug)]
+pub struct Index {
+  config: ScoringConfig,
+  candidates: Vec<IndexedCandidate>,
+}
+
+#[derive(Clone, Debug)]
+struct IndexedCandidate {
+  text: String,
+  prepared: PreparedCandidate,
+}
+
+impl Index {
+  pub fn new(config: &ScoringConfig) -> Self {
+    Self {
+      config: config.clone(),
+      candidates: Vec::new(),
+    }
+  }
+
+  pub fn config(&self) -> &ScoringConfig {
+    &self.config
+  }
+
+  pub fn len(&self) -> usize {
+    self.candidates.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.candidates.is_empty()
+  }
+
+  /// The candidate at `index`, if there is one
+  pub fn get(&self, index: usize) -> Option<&str> {
+    self.candidates.get(index).map(|candidate| &candidate.text[..])
+  }
+
+  /// The candidates, in the order they were added
+  pub fn iter(&self) -> impl Iterator<Item = &str> {
+    self.candidates.iter().map(|candidate| &candidate.text[..])
+  }
+
+  /// Adds a candidate after all the others
+  pub fn push<S: Into<String>>(&mut self, candidate: S) {
+    let text = candidate.into();
+    let prepared = PreparedCandidate::new(&text, &self.config);
+    self.candidates.push(IndexedCandidate { text, prepared });
+  }
+
+  /// Removes and returns the candidate at `index`, moving every later
+  /// candidate down one
+  ///
+  /// # Panics
+  ///
+  /// Panics if `index` is out of bounds.
+  pub fn remove(&mut self, index: usize) -> String {
+    self.candidates.remove(index).text
+  }
+
+  /// Search among the candidates using the given query, returning an
+  /// ordered collection of results (highest score first)
+  pub fn search(&self, query: &Query, parallelism: usize) -> ScoreResults {
+    self.search_top(query, usize::MAX, parallelism)
+  }
+
+  /// As `search`, returning only the `k` best results
+  pub fn search_top(&self, query: &Query, k: usize, parallelism: usize) -> ScoreResults {
+    let search = |matcher: &mut Matcher, candidate: &IndexedCandidate, index: usize| {
+      if matcher.has_match_cached(&candidate.text, &candidate.prepared) {
+        Some(matcher.score_cached(&candidate.text, &candidate.prepared, index))
+      } else {
+        None
+      }
+    };
+    search_internal(query, &self.candidates, k, parallelism, &self.config, &search, &Hooks::none())
+      .take(k)
+      .collect()
+  }
+
+  /// Search among the candidates using the given query, returning an
+  /// ordered collection of results (highest score first) with the locations
+  /// of the query in each candidate
+  pub fn locate(&self, query: &Query, parallelism: usize) -> LocateResults {
+    self.locate_top(query, usize::MAX, parallelism)
+  }
+
+  /// As `locate`, returning only the `k` best results
+  pub fn locate_top(&self, query: &Query, k: usize, parallelism: usize) -> LocateResults {
+    let search = |matcher: &mut Matcher, candidate: &IndexedCandidate, index: usize| {
+      if matcher.has_match_cached(&candidate.text, &candidate.prepared) {
+        Some(matcher.locate_cached(&candidate.text, &candidate.prepared, index))
+      } else {
+        None
+      }
+    };
+    search_internal(query, &self.candidates, k, parallelism, &self.config, &search, &Hooks::none())
+      .take(k)
+      .collect()
+  }
+
+  // The indices of the candidates that match `query`, out of those at
+  // `among` if given, stopping early if `cancel` is cancelled
+  pub(super) fn matching(
+    &self,
+    query: &Query,
+    among: Option<&[usize]>,
+    cancel: Option<&CancellationToken>,
+  ) -> Vec<usize> {
+    match among {
+      Some(among) => self.filter_matching(query, among.iter().cloned(), cancel),
+      None => self.filter_matching(query, 0..self.candidates.len(), cancel),
+    }
+  }
+
+  fn filter_matching<I>(
+    &self,
+    query: &Query,
+    indices: I,
+    cancel: Option<&CancellationToken>,
+  ) -> Vec<usize>
+  where
+    I: Iterator<Item = usize>,
+  {
+    let mut matcher = Matcher::new(query, &self.config);
+    indices
+      .enumerate()
+      .take_while(|&(i, _)| i % CANCEL_CHECK_INTERVAL != 0 || !is_cancelled(cancel))
+      .map(|(_, index)| index)
+      .filter(|&index| {
+        let candidate = &self.candidates[index];
+        matcher.has_match_cached(&candidate.text, &candidate.prepared)
+      })
+      .collect()
+  }
+
+  // Searches only the candidates at `indices`, which all match `query`
+  pub(super) fn search_among<T>(
+    &self,
+    query: &Query,
+    indices: &[usize],
+    k: usize,
+    parallelism: usize,
+    search_fn: CachedFn<T>,
+    cancel: Option<&CancellationToken>,
+  ) -> Vec<T>
+  where
+    T: Ord + Send + 'static,
+  {
+    let search = |matcher: &mut Matcher, &index: &usize, _| {
+      let candidate = &self.candidates[index];
+      Some(search_fn(matcher, &candidate.text, &candidate.prepared, index))
+    };
+    let hooks = Hooks {
+      cancel,
+      progress: None,
+    };
+    search_internal(query, indices, k, parallelism, &self.config, &search, &hooks)
+      .take(k)
+      .collect()
+  }
+}
+
+impl<S: Into<String>> Extend<S> for Index {
+  fn extend<I: IntoIterator<Item = S>>(&mut self, candidates: I) {
+    for candidate in candidates {
+      self.push(candidate);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use score::config::PathScoring;
+  use score::{has_match, CaseMatching, Normalization, QueryOptions};
+
+  const CANDIDATES: &[&str] = &[
+    "src/score/matcher.rs",
+    "src/Search/Index.rs",
+    "Cargo.toml",
+    "README.md",
+    "docs/Ñandú.md",
+    "docs/cafe\u{301}.md",
+    "tests/ÀÉÎ.rs",
+    "",
+  ];
+
+  fn indices<R>(results: &[R], index: fn(&R) -> usize) -> Vec<usize> {
+    results.iter().map(index).collect()
+  }
+
+  // The index gives exactly what searching the candidates directly does
+  fn assert_same(index: &Index, query: &Query) {
+    let candidates: Vec<&str> = index.iter().collect();
+    for &parallelism in &[1, 4] {
+      let expected = ::search::search_score(query, &candidates, parallelism, index.config());
+      let results = index.search(query, parallelism);
+      assert_eq!(expected.len(), results.len());
+      for (expected, result) in expected.iter().zip(&results) {
+        assert_eq!(expected.candidate_index, result.candidate_index);
+        assert_eq!(expected.score, result.score);
+      }
+
+      let expected = ::search::search_locate(query, &candidates, parallelism, index.config());
+      let results = index.locate(query, parallelism);
+      assert_eq!(expected.len(), results.len());
+      for (expected, result) in expected.iter().zip(&results) {
+        assert_eq!(expected.candidate_index, result.candidate_index);
+        assert_eq!(expected.score, result.score);
+        assert_eq!(expected.match_mask, result.match_mask);
+      }
+    }
+  }
+
+  #[test]
+  fn same_as_searching() {
+    let path = ScoringConfig {
+      path: Some(PathScoring::default()),
+      ..ScoringConfig::default()
+    };
+    let options = [
+      QueryOptions::default(),
+      QueryOptions {
+        case: CaseMatching::Sensitive,
+        ..QueryOptions::default()
+      },
+      QueryOptions {
+        normalization: Normalization::Nfc,
+        strip_diacritics: true,
+        ..QueryOptions::default()
+      },
+      QueryOptions {
+        graphemes: true,
+        ..QueryOptions::default()
+      },
+      QueryOptions {
+        extended: true,
+        ..QueryOptions::default()
+      },
+      // Normalized differently from the candidates cached for the last one
+      QueryOptions {
+        normalization: Normalization::Nfkc,
+        case: CaseMatching::Sensitive,
+        extended: true,
+        ..QueryOptions::default()
+      },
+    ];
+
+    for config in &[ScoringConfig::default(), path] {
+      let mut index = Index::new(config);
+      index.extend(CANDIDATES.iter().cloned());
+      assert_eq!(CANDIDATES.len(), index.len());
+
+      for options in &options {
+        for query in &["", "rs", "SI", "sim", "ñ", "Ñand", "aei", "café", "e\u{301}", "é"] {
+          assert_same(&index, &Query::with_options(query, options));
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn matching_uses_cache() {
+    let config = ScoringConfig::default();
+    let mut index = Index::new(&config);
+    index.extend(CANDIDATES.iter().cloned());
+    let all: Vec<usize> = (0..index.len()).collect();
+
+    for &normalization in &[Normalization::Nfc, Normalization::Nfkc, Normalization::None] {
+      for &case in &[CaseMatching::Smart, CaseMatching::Sensitive] {
+        for &extended in &[false, true] {
+          let options = QueryOptions {
+            normalization,
+            strip_diacritics: normalization == Normalization::Nfc,
+            case,
+            extended,
+            ..QueryOptions::default()
+          };
+          for query in &["rs", "SI", "ñ", "Ñand", "aei", "café", "^docs | é$", "!rs"] {
+            let query = Query::with_options(query, &options);
+            let expected: Vec<usize> = all
+              .iter()
+              .cloned()
+              .filter(|&i| has_match(&query, index.get(i).unwrap()))
+              .collect();
+            assert_eq!(expected, index.matching(&query, None, None), "{:?}", query);
+          }
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn push_and_remove() {
+    let config = ScoringConfig::default();
+    let mut index = Index::new(&config);
+    assert!(index.is_empty());
+    index.push("tags");
+    index.push(String::from("test"));
+    index.extend(vec!["attest", "stub"]);
+    assert_eq!(Some("attest"), index.get(2));
+
+    let query = Query::new("te");
+    assert_eq!(vec![1, 2], indices(&index.search(&query, 1), |r| r.candidate_index));
+    assert_eq!(vec![1], indices(&index.locate_top(&query, 1, 1), |r| r.candidate_index));
+
+    assert_eq!("test", index.remove(1));
+    assert_eq!(3, index.len());
+    assert_eq!(vec![1], indices(&index.search(&query, 1), |r| r.candidate_index));
+    assert_eq!(Some("attest"), index.get(1));
+    assert_eq!(None, index.get(3));
+  }
+}
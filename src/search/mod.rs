@@ -1,14 +1,45 @@
 extern crate crossbeam;
 extern crate itertools;
 
-use std::cmp::Ordering;
-use std::usize;
-
-use self::crossbeam::channel;
-use self::crossbeam::scope as thread_scope;
+mod cancel;
+#[cfg(feature = "bench")]
+mod equal_split;
+mod fields;
+mod index;
+mod pool;
+mod progress;
+mod session;
+#[cfg(feature = "async")]
+mod stream;
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::iter;
+use std::ops::{Deref, Range};
+use std::sync::Mutex;
+
+use self::crossbeam::deque::{self, Stealer};
 use self::itertools::kmerge;
 
-use score::{has_match, locate_inner, score_inner, LocateResult, ScoreResult};
+use score::config::ScoringConfig;
+use score::{has_match, LocateResult, Matcher, Query, ScoreResult};
+
+pub use self::cancel::{Cancelled, CancellationToken};
+#[cfg(feature = "bench")]
+pub use self::equal_split::search_score_equal_split;
+use self::cancel::{is_cancelled, unless_cancelled, CANCEL_CHECK_INTERVAL};
+pub use self::fields::{search_fields, Field, FieldKey, FieldResult, FieldScoring};
+pub use self::index::Index;
+pub use self::session::{Feed, Session};
+#[cfg(feature = "async")]
+pub use self::stream::{search_locate_stream, search_score_stream, Panicked, SearchStream};
+use self::pool::Pool;
+pub use self::progress::Progress;
+use self::progress::{reporter, Report, Snapshot};
+
+// How many chunks each thread's share of the candidates is split into, at
+// most `CANCEL_CHECK_INTERVAL` candidates long
+const CHUNKS_PER_THREAD: usize = 16;
 
 /// Collection of scores and the candidates they apply to
 pub type ScoreResults = Vec<ScoreResult>;
@@ -17,94 +48,436 @@ pub type LocateResults = Vec<LocateResult>;
 
 /// Search among a collection of candidates using the given query, returning
 /// an ordered collection of results (highest score first)
-pub fn search_score(
-  query: &str,
-  candidates: &[&str],
+pub fn search_score<S: AsRef<str> + Sync>(
+  query: &Query,
+  candidates: &[S],
+  parallelism: usize,
+  config: &ScoringConfig,
+) -> ScoreResults {
+  search_score_iter(query, candidates, parallelism, config).collect()
+}
+
+/// Search among a collection of candidates using the given query, returning
+/// an iterator over the results (highest score first)
+///
+/// This doesn't return until every candidate has been scored and every match
+/// kept, since the best result could be any of them. Only putting the matches
+/// in order waits until they're taken, so taking the first few saves sorting
+/// the rest but not searching them. To keep only the best few while searching,
+/// use `search_score_top`.
+pub fn search_score_iter<S: AsRef<str> + Sync>(
+  query: &Query,
+  candidates: &[S],
+  parallelism: usize,
+  config: &ScoringConfig,
+) -> Box<dyn Iterator<Item = ScoreResult> + Send> {
+  let search = by_key(query, &as_str, Matcher::score_indexed);
+  search_internal(query, candidates, usize::MAX, parallelism, config, &search, &Hooks::none())
+}
+
+/// Search among a collection of candidates using the given query, returning
+/// only the `k` best results (highest score first)
+///
+/// The results are the first `k` of `search_score`, but each thread only keeps
+/// its best `k` rather than sorting all of its matches.
+pub fn search_score_top<S: AsRef<str> + Sync>(
+  query: &Query,
+  candidates: &[S],
+  k: usize,
   parallelism: usize,
+  config: &ScoringConfig,
 ) -> ScoreResults {
-  search_internal(query, candidates, parallelism, score_inner).collect()
+  let search = by_key(query, &as_str, Matcher::score_indexed);
+  search_internal(query, candidates, k, parallelism, config, &search, &Hooks::none())
+    .take(k)
+    .collect()
 }
 
 /// Search among a collection of candidates using the given query, returning
 /// an ordered collection of results (highest score first) with the locations
 /// of the query in each candidate
-pub fn search_locate(
-  query: &str,
-  candidates: &[&str],
+pub fn search_locate<S: AsRef<str> + Sync>(
+  query: &Query,
+  candidates: &[S],
+  parallelism: usize,
+  config: &ScoringConfig,
+) -> LocateResults {
+  search_locate_iter(query, candidates, parallelism, config).collect()
+}
+
+/// Search among a collection of candidates using the given query, returning
+/// an iterator over the results (highest score first) with the locations of
+/// the query in each candidate
+///
+/// As `search_score_iter`, every candidate is searched and located before
+/// this returns, and only putting the results in order waits until they're
+/// taken.
+pub fn search_locate_iter<S: AsRef<str> + Sync>(
+  query: &Query,
+  candidates: &[S],
+  parallelism: usize,
+  config: &ScoringConfig,
+) -> Box<dyn Iterator<Item = LocateResult> + Send> {
+  let search = by_key(query, &as_str, Matcher::locate_indexed);
+  search_internal(query, candidates, usize::MAX, parallelism, config, &search, &Hooks::none())
+}
+
+/// Search among a collection of candidates using the given query, returning
+/// only the `k` best results (highest score first) with the locations of the
+/// query in each candidate
+///
+/// As `search_score_top`, the results are the first `k` of `search_locate`.
+pub fn search_locate_top<S: AsRef<str> + Sync>(
+  query: &Query,
+  candidates: &[S],
+  k: usize,
   parallelism: usize,
+  config: &ScoringConfig,
 ) -> LocateResults {
-  search_internal(query, candidates, parallelism, locate_inner).collect()
+  let search = by_key(query, &as_str, Matcher::locate_indexed);
+  search_internal(query, candidates, k, parallelism, config, &search, &Hooks::none())
+    .take(k)
+    .collect()
+}
+
+/// As `search_score`, giving up with `Cancelled` if `cancel` is cancelled
+/// before the search finishes
+pub fn search_score_cancellable<S: AsRef<str> + Sync>(
+  query: &Query,
+  candidates: &[S],
+  parallelism: usize,
+  config: &ScoringConfig,
+  cancel: &CancellationToken,
+) -> Result<ScoreResults, Cancelled> {
+  let search = by_key(query, &as_str, Matcher::score_indexed);
+  let results = search_internal(
+    query,
+    candidates,
+    usize::MAX,
+    parallelism,
+    config,
+    &search,
+    &Hooks::cancel(cancel),
+  );
+  unless_cancelled(results.collect(), cancel)
+}
+
+/// As `search_locate`, giving up with `Cancelled` if `cancel` is cancelled
+/// before the search finishes
+pub fn search_locate_cancellable<S: AsRef<str> + Sync>(
+  query: &Query,
+  candidates: &[S],
+  parallelism: usize,
+  config: &ScoringConfig,
+  cancel: &CancellationToken,
+) -> Result<LocateResults, Cancelled> {
+  let search = by_key(query, &as_str, Matcher::locate_indexed);
+  let results = search_internal(
+    query,
+    candidates,
+    usize::MAX,
+    parallelism,
+    config,
+    &search,
+    &Hooks::cancel(cancel),
+  );
+  unless_cancelled(results.collect(), cancel)
+}
+
+/// As `search_score_top`, calling `on_progress` from each thread of the
+/// search after each chunk of candidates it searches
+///
+/// Each thread reports how far it has gotten and its best `k` results so far,
+/// so that the best results yet can be shown before the search finishes.
+/// `on_progress` is called from many threads at once, so it should be quick.
+/// It can search too, but a search started from within another runs all on
+/// the thread that starts it, whatever its `parallelism`.
+pub fn search_score_progress<S, P>(
+  query: &Query,
+  candidates: &[S],
+  k: usize,
+  parallelism: usize,
+  config: &ScoringConfig,
+  on_progress: P,
+) -> ScoreResults
+where
+  S: AsRef<str> + Sync,
+  P: Fn(Progress<ScoreResult>) + Sync,
+{
+  let search = by_key(query, &as_str, Matcher::score_indexed);
+  let report = reporter(candidates.len(), k, &on_progress);
+  let hooks = Hooks {
+    cancel: None,
+    progress: Some(&report),
+  };
+  search_internal(query, candidates, k, parallelism, config, &search, &hooks)
+    .take(k)
+    .collect()
+}
+
+/// As `search_locate_top`, calling `on_progress` from each thread of the
+/// search after each chunk of candidates it searches
+///
+/// See `search_score_progress`.
+pub fn search_locate_progress<S, P>(
+  query: &Query,
+  candidates: &[S],
+  k: usize,
+  parallelism: usize,
+  config: &ScoringConfig,
+  on_progress: P,
+) -> LocateResults
+where
+  S: AsRef<str> + Sync,
+  P: Fn(Progress<LocateResult>) + Sync,
+{
+  let search = by_key(query, &as_str, Matcher::locate_indexed);
+  let report = reporter(candidates.len(), k, &on_progress);
+  let hooks = Hooks {
+    cancel: None,
+    progress: Some(&report),
+  };
+  search_internal(query, candidates, k, parallelism, config, &search, &hooks)
+    .take(k)
+    .collect()
 }
 
-fn search_internal<T>(
-  query: &str,
-  candidates: &[&str],
+/// Search among a collection of items by the text `key` gives for each,
+/// returning an ordered collection of results (highest score first) along
+/// with the items they are for
+///
+/// `key` is called from each thread of the search. It can search too, but a
+/// search started from within another runs all on the thread that starts it.
+pub fn search_score_by<'a, C, K>(
+  query: &Query,
+  items: &'a [C],
+  key: K,
   parallelism: usize,
-  search_fn: fn(&str, &str, usize) -> T,
-) -> Box<dyn Iterator<Item = T>>
+  config: &ScoringConfig,
+) -> Vec<ItemResult<'a, C, ScoreResult>>
 where
-  T: PartialOrd + Sized + Send + 'static,
+  C: Sync,
+  K: Fn(&C) -> &str + Sync,
+{
+  let search = by_key(query, &key, Matcher::score_indexed);
+  search_internal(query, items, usize::MAX, parallelism, config, &search, &Hooks::none())
+    .map(|result| ItemResult {
+      item: &items[result.candidate_index],
+      result,
+    })
+    .collect()
+}
+
+/// Search among a collection of items by the text `key` gives for each,
+/// returning an ordered collection of results (highest score first) with the
+/// locations of the query in the text of each item, along with the items they
+/// are for
+///
+/// See `search_score_by`.
+pub fn search_locate_by<'a, C, K>(
+  query: &Query,
+  items: &'a [C],
+  key: K,
+  parallelism: usize,
+  config: &ScoringConfig,
+) -> Vec<ItemResult<'a, C, LocateResult>>
+where
+  C: Sync,
+  K: Fn(&C) -> &str + Sync,
+{
+  let search = by_key(query, &key, Matcher::locate_indexed);
+  search_internal(query, items, usize::MAX, parallelism, config, &search, &Hooks::none())
+    .map(|result| ItemResult {
+      item: &items[result.candidate_index],
+      result,
+    })
+    .collect()
+}
+
+/// A search result, along with the item it is for
+///
+/// This dereferences to the result, so `result.score` gives its score.
+#[derive(Debug)]
+pub struct ItemResult<'a, C: 'a, R> {
+  pub item: &'a C,
+  pub result: R,
+}
+
+impl<'a, C, R> Deref for ItemResult<'a, C, R> {
+  type Target = R;
+
+  fn deref(&self) -> &R {
+    &self.result
+  }
+}
+
+fn as_str<S: AsRef<str>>(candidate: &S) -> &str {
+  candidate.as_ref()
+}
+
+// Searches the text `key` gives for each candidate with `search_fn`, if it
+// matches at all
+fn by_key<'k, C, K, T>(
+  query: &'k Query,
+  key: &'k K,
+  search_fn: fn(&mut Matcher, &str, usize) -> T,
+) -> impl Fn(&mut Matcher, &C, usize) -> Option<T> + Sync + 'k
+where
+  K: Fn(&C) -> &str + Sync,
+  T: 'k,
+{
+  move |matcher, candidate, index| {
+    let candidate = key(candidate);
+    if has_match(query, candidate) {
+      Some(search_fn(matcher, candidate, index))
+    } else {
+      None
+    }
+  }
+}
+
+// Ways of following or stopping a search from outside it
+struct Hooks<'h, T: 'h> {
+  cancel: Option<&'h CancellationToken>,
+  // Called by each thread after each chunk of candidates it searches
+  progress: Option<&'h Report<'h, T>>,
+}
+
+impl<'h, T> Hooks<'h, T> {
+  fn none() -> Self {
+    Self {
+      cancel: None,
+      progress: None,
+    }
+  }
+
+  fn cancel(cancel: &'h CancellationToken) -> Self {
+    Self {
+      cancel: Some(cancel),
+      progress: None,
+    }
+  }
+}
+
+// Searches in `parallelism` threads, each of which gives at most its best
+// `limit` results, merged best first as they're taken
+//
+// The candidates are split into small chunks, and each thread starts with an
+// equal share of them. Threads that finish their share take chunks from the
+// others, so that a share with many slow candidates doesn't hold up the
+// search.
+fn search_internal<C, F, T>(
+  query: &Query,
+  candidates: &[C],
+  limit: usize,
+  parallelism: usize,
+  config: &ScoringConfig,
+  search: &F,
+  hooks: &Hooks<T>,
+) -> Box<dyn Iterator<Item = T> + Send>
+where
+  C: Sync,
+  F: Fn(&mut Matcher, &C, usize) -> Option<T> + Sync,
+  T: Ord + Sized + Send + 'static,
 {
   let parallelism = calculate_parallelism(candidates.len(), parallelism, query.is_empty());
-  let mut candidates = candidates;
-  let (sender, receiver) = channel::bounded::<Vec<T>>(parallelism);
+  let chunk_len = ceil_div(candidates.len(), parallelism * CHUNKS_PER_THREAD)
+    .clamp(1, CANCEL_CHECK_INTERVAL);
+  let chunks = (0..candidates.len())
+    .step_by(chunk_len)
+    .map(|start| start..(start + chunk_len).min(candidates.len()));
 
   if parallelism < 2 {
-    Box::new(search_worker(candidates, query, 0, search_fn).into_iter())
-  } else {
-    thread_scope(|scope| {
-      let mut remaining_candidates = candidates.len();
-      let per_thread_count = ceil_div(remaining_candidates, parallelism);
-      let mut thread_offset = 0;
-
-      // Create "parallelism" threads
-      while remaining_candidates > 0 {
-        // Search in this thread's share
-        let split = if remaining_candidates >= per_thread_count {
-          remaining_candidates -= per_thread_count;
-          per_thread_count
-        } else {
-          remaining_candidates = 0;
-          remaining_candidates
-        };
-        let split = candidates.split_at(split);
-        let splitted_len = split.0.len();
-        let sender = sender.clone();
-        scope.spawn(move || {
-          sender.send(search_worker(split.0, query, thread_offset, search_fn));
-        });
-        thread_offset += splitted_len;
-
-        // Remove that share from the candidate slice
-        candidates = split.1;
-      }
+    let matcher = Matcher::new(query, config);
+    let results = search_worker(0, candidates, chunks, matcher, limit, search, hooks);
+    return Box::new(BestFirst(results));
+  }
 
-      drop(sender);
+  let shares: Vec<_> = (0..parallelism).map(|_| deque::fifo()).collect();
+  let per_thread_count = ceil_div(chunks.len(), parallelism);
+  for (i, chunk) in chunks.enumerate() {
+    shares[i / per_thread_count].0.push(chunk);
+  }
+  let stealers: Vec<Stealer<Range<usize>>> = shares.iter().map(|share| share.1.clone()).collect();
+
+  let results = Mutex::new(Vec::with_capacity(parallelism));
+  Pool::global().run(parallelism, &|worker| {
+    // This thread's share first, then whatever is left of the others
+    let chunks = iter::from_fn(|| {
+      stealers[worker..]
+        .iter()
+        .chain(&stealers[..worker])
+        .find_map(Stealer::steal)
     });
+    let matcher = Matcher::new(query, config);
+    let found = search_worker(worker, candidates, chunks, matcher, limit, search, hooks);
+    results.lock().unwrap().push(found);
+  });
 
-    Box::new(kmerge(receiver))
-  }
+  Box::new(kmerge(results.into_inner().unwrap().into_iter().map(BestFirst)))
 }
 
-// Search among candidates against a query in a single thread
-fn search_worker<T>(
-  candidates: &[&str],
-  query: &str,
-  offset_index: usize,
-  search_fn: fn(&str, &str, usize) -> T
-) -> Vec<T>
+// Search among chunks of candidates in a single thread, keeping the best
+// `limit` results in a heap with the best on top
+fn search_worker<C, I, F, T>(
+  worker: usize,
+  candidates: &[C],
+  chunks: I,
+  mut matcher: Matcher,
+  limit: usize,
+  search: &F,
+  hooks: &Hooks<T>,
+) -> BinaryHeap<Reverse<T>>
 where
-  T: PartialOrd,
+  I: Iterator<Item = Range<usize>>,
+  F: Fn(&mut Matcher, &C, usize) -> Option<T>,
+  T: Ord,
 {
-  let mut out = Vec::with_capacity(candidates.len());
-  for (index, candidate) in candidates.into_iter().enumerate() {
-    if has_match(&query, candidate) {
-      out.push(search_fn(&query, candidate, offset_index + index));
+  // Results order best first, so the top of this heap is the worst kept
+  let mut best = BinaryHeap::new();
+  let mut searched = 0;
+  let mut matched = 0;
+  for chunk in chunks {
+    if is_cancelled(hooks.cancel) {
+      break;
+    }
+
+    searched += chunk.len();
+    for (candidate, index) in candidates[chunk.clone()].iter().zip(chunk) {
+      if let Some(result) = search(&mut matcher, candidate, index) {
+        matched += 1;
+        best.push(result);
+        if best.len() > limit {
+          best.pop();
+        }
+      }
+    }
+
+    if let Some(progress) = hooks.progress {
+      progress(Snapshot {
+        worker,
+        searched,
+        matched,
+        kept: best.as_slice(),
+      });
     }
   }
-  out.sort_unstable_by(|result1, result2| result1.partial_cmp(result2).unwrap_or(Ordering::Less));
+  best.into_iter().map(Reverse).collect()
+}
+
+// Takes results from a heap best first
+struct BestFirst<T: Ord>(BinaryHeap<Reverse<T>>);
 
-  out
+impl<T: Ord> Iterator for BestFirst<T> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    self.0.pop().map(|Reverse(result)| result)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.0.len(), Some(self.0.len()))
+  }
 }
 
 fn calculate_parallelism(
@@ -132,12 +505,22 @@ fn calculate_parallelism(
 
 /// Integer ceiling division
 fn ceil_div(a: usize, b: usize) -> usize {
-  (a + b - 1) / b
+  a.div_ceil(b)
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use score::config::TieBreak;
+
+  fn search_score(query: &str, candidates: &[&str], parallelism: usize) -> ScoreResults {
+    super::search_score(
+      &Query::new(query),
+      candidates,
+      parallelism,
+      &ScoringConfig::default(),
+    )
+  }
 
   #[test]
   fn parallelism_ramp() {
@@ -308,4 +691,295 @@ mod tests {
     search_med_parallelism(4);
     search_large_parallelism(16);
   }
+
+  #[test]
+  fn deterministic_order() {
+    // Lots of candidates with the same score, of different lengths
+    let n = 5_000;
+    let candidates: Vec<String> = (0..n)
+      .map(|i| format!("{}ab{}", i % 7, "x".repeat(i % 5)))
+      .collect();
+    let candidates: Vec<&str> = candidates.iter().map(|s| &s[..]).collect();
+    let query = Query::new("ab");
+
+    for tie_breaks in &[
+      vec![TieBreak::Length, TieBreak::Index],
+      vec![TieBreak::Index],
+      vec![TieBreak::Span, TieBreak::Length],
+      vec![],
+    ] {
+      let config = ScoringConfig {
+        tie_breaks: tie_breaks.clone(),
+        ..ScoringConfig::default()
+      };
+      let indices = |parallelism| {
+        let scored: Vec<usize> = super::search_score(&query, &candidates, parallelism, &config)
+          .iter()
+          .map(|r| r.candidate_index)
+          .collect();
+        let located: Vec<usize> = super::search_locate(&query, &candidates, parallelism, &config)
+          .iter()
+          .map(|r| r.candidate_index)
+          .collect();
+        (scored, located)
+      };
+
+      let expected = indices(1);
+      assert_eq!(n, expected.0.len());
+      for parallelism in 2..17 {
+        assert_eq!(expected, indices(parallelism), "{:?} {}", tie_breaks, parallelism);
+      }
+    }
+  }
+
+  #[test]
+  fn tie_breaks() {
+    // Without gap penalties after the first match, these all score the same
+    let config = ScoringConfig {
+      gap_inner: 0.0,
+      gap_trailing: 0.0,
+      match_consecutive: 0.0,
+      ..ScoringConfig::default()
+    };
+    let candidates = &["xabyy", "xab", "xayyb", "xab", "xayb"];
+    let order = |tie_breaks: &[TieBreak]| {
+      let config = ScoringConfig {
+        tie_breaks: tie_breaks.to_vec(),
+        ..config.clone()
+      };
+      let scored: Vec<usize> = super::search_score(&Query::new("ab"), candidates, 1, &config)
+        .iter()
+        .map(|r| r.candidate_index)
+        .collect();
+      let located: Vec<usize> = super::search_locate(&Query::new("ab"), candidates, 1, &config)
+        .iter()
+        .map(|r| r.candidate_index)
+        .collect();
+      (scored, located)
+    };
+
+    let by_length = (vec![1, 3, 4, 0, 2], vec![1, 3, 4, 0, 2]);
+    assert_eq!(by_length, order(&[TieBreak::Length, TieBreak::Index]));
+    assert_eq!(by_length, order(&[TieBreak::Length]));
+    assert_eq!(by_length, order(&[TieBreak::Length, TieBreak::Length, TieBreak::Index]));
+
+    let by_index = (vec![0, 1, 2, 3, 4], vec![0, 1, 2, 3, 4]);
+    assert_eq!(by_index, order(&[TieBreak::Index, TieBreak::Length]));
+    assert_eq!(by_index, order(&[]));
+
+    // Scored results are ordered by span as located ones are
+    assert_eq!(
+      (vec![1, 3, 0, 4, 2], vec![1, 3, 0, 4, 2]),
+      order(&[TieBreak::Span, TieBreak::Length])
+    );
+    assert_eq!(
+      (vec![0, 1, 3, 4, 2], vec![0, 1, 3, 4, 2]),
+      order(&[TieBreak::Span, TieBreak::Index, TieBreak::Span, TieBreak::Length])
+    );
+
+    // Spans are counted in the original characters of non-ASCII candidates
+    let config = ScoringConfig {
+      tie_breaks: vec![TieBreak::Span],
+      ..config
+    };
+    let candidates = &["éaéébé", "éaéb", "éaéééb", "éab"];
+    let scored: Vec<usize> = super::search_score(&Query::new("ab"), candidates, 1, &config)
+      .iter()
+      .map(|r| r.candidate_index)
+      .collect();
+    let located: Vec<usize> = super::search_locate(&Query::new("ab"), candidates, 1, &config)
+      .iter()
+      .map(|r| r.candidate_index)
+      .collect();
+    assert_eq!(vec![3, 1, 0, 2], scored);
+    assert_eq!(scored, located);
+  }
+
+  #[test]
+  fn top_results() {
+    let n = 20_000;
+    let candidates: Vec<String> = (0..n).map(|i| format!("{}", i * 7)).collect();
+    let candidates: Vec<&str> = candidates.iter().map(|s| &s[..]).collect();
+    let query = Query::new("12");
+    let config = ScoringConfig::default();
+
+    let all: Vec<usize> = super::search_score(&query, &candidates, 4, &config)
+      .iter()
+      .map(|r| r.candidate_index)
+      .collect();
+    for &k in &[0, 1, 10, 1000, all.len(), n + 1] {
+      for &parallelism in &[1, 3, 8] {
+        let scored: Vec<usize> = search_score_top(&query, &candidates, k, parallelism, &config)
+          .iter()
+          .map(|r| r.candidate_index)
+          .collect();
+        let located: Vec<usize> = search_locate_top(&query, &candidates, k, parallelism, &config)
+          .iter()
+          .map(|r| r.candidate_index)
+          .collect();
+        assert_eq!(&all[..k.min(all.len())], &scored[..]);
+        assert_eq!(scored, located);
+      }
+    }
+  }
+
+  #[test]
+  fn generic_candidates() {
+    let owned: Vec<String> = vec!["tags".to_string(), "test".to_string()];
+    let config = ScoringConfig::default();
+    let rs = super::search_score(&Query::new("te"), &owned, 1, &config);
+    assert_eq!(1, rs.len());
+    assert_eq!(1, rs[0].candidate_index);
+
+    #[derive(Debug, PartialEq)]
+    struct Record {
+      id: u32,
+      name: String,
+    }
+    let records: Vec<Record> = (0..40)
+      .map(|id| Record {
+        id,
+        name: format!("record {}", id),
+      })
+      .collect();
+
+    for parallelism in 1..5 {
+      let rs = search_score_by(&Query::new("d 3"), &records, |r| &r.name, parallelism, &config);
+      assert_eq!(13, rs.len());
+      assert_eq!(3, rs[0].item.id);
+      for result in &rs {
+        assert_eq!(&records[result.candidate_index], result.item);
+      }
+
+      let rs = search_locate_by(&Query::new("d 3"), &records, |r| &r.name, parallelism, &config);
+      assert_eq!(13, rs.len());
+      assert_eq!(3, rs[0].item.id);
+      assert_eq!(vec![5..8], rs[0].match_ranges(&rs[0].item.name));
+    }
+  }
+
+  #[test]
+  fn cancellation() {
+    let candidates: Vec<String> = (0..10_000).map(|i| format!("{}", i)).collect();
+    let query = Query::new("12");
+    let config = ScoringConfig::default();
+
+    for parallelism in 1..5 {
+      let token = CancellationToken::new();
+      let expected = super::search_score(&query, &candidates, parallelism, &config);
+      let results =
+        search_score_cancellable(&query, &candidates, parallelism, &config, &token).unwrap();
+      assert_eq!(
+        expected.iter().map(|r| r.candidate_index).collect::<Vec<_>>(),
+        results.iter().map(|r| r.candidate_index).collect::<Vec<_>>()
+      );
+      let results =
+        search_locate_cancellable(&query, &candidates, parallelism, &config, &token).unwrap();
+      assert_eq!(expected.len(), results.len());
+
+      token.cancel();
+      assert_eq!(
+        Err(Cancelled),
+        search_score_cancellable(&query, &candidates, parallelism, &config, &token)
+      );
+      let results = search_locate_cancellable(&query, &candidates, parallelism, &config, &token);
+      assert!(results.is_err());
+    }
+  }
+
+  #[test]
+  fn lazy_results() {
+    let candidates: Vec<String> = (0..5_000).map(|i| format!("{}/{}", i % 17, i)).collect();
+    let config = ScoringConfig::default();
+
+    for parallelism in 1..5 {
+      for query in &["", "1", "12", "1/2", "nothing"] {
+        let query = Query::new(query);
+        let expected = super::search_score(&query, &candidates, parallelism, &config);
+        let mut results = search_score_iter(&query, &candidates, parallelism, &config);
+        let first: Vec<usize> = results.by_ref().take(10).map(|r| r.candidate_index).collect();
+        let rest: Vec<usize> = results.map(|r| r.candidate_index).collect();
+        assert_eq!(
+          expected.iter().map(|r| r.candidate_index).collect::<Vec<_>>(),
+          [first, rest].concat()
+        );
+
+        let expected = super::search_locate(&query, &candidates, parallelism, &config);
+        let results: Vec<_> = search_locate_iter(&query, &candidates, parallelism, &config)
+          .take(3)
+          .collect();
+        assert_eq!(expected.len().min(3), results.len());
+        for (expected, result) in expected.iter().zip(&results) {
+          assert_eq!(expected.candidate_index, result.candidate_index);
+          assert_eq!(expected.match_mask, result.match_mask);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn progress() {
+    let candidates: Vec<String> = (0..10_000).map(|i| format!("{}/{}", i % 17, i)).collect();
+    let config = ScoringConfig::default();
+    let query = Query::new("1/2");
+    let matches = super::search_score(&query, &candidates, 1, &config).len();
+
+    for parallelism in 1..5 {
+      let reports = Mutex::new(Vec::new());
+      let results = search_score_progress(&query, &candidates, 10, parallelism, &config, |p| {
+        reports.lock().unwrap().push(p)
+      });
+      let expected = search_score_top(&query, &candidates, 10, parallelism, &config);
+      assert_eq!(
+        expected.iter().map(|r| r.candidate_index).collect::<Vec<_>>(),
+        results.iter().map(|r| r.candidate_index).collect::<Vec<_>>()
+      );
+
+      // The last report from each thread covers everything it searched
+      let reports = reports.into_inner().unwrap();
+      let mut last: Vec<&Progress<ScoreResult>> = Vec::new();
+      for report in &reports {
+        assert_eq!(candidates.len(), report.total);
+        assert!(report.best.len() <= 10);
+        assert!(report.best.windows(2).all(|pair| pair[0] < pair[1]));
+        match last.iter_mut().find(|p| p.worker == report.worker) {
+          Some(previous) => {
+            assert!(previous.searched < report.searched);
+            *previous = report;
+          }
+          None => last.push(report),
+        }
+      }
+      assert_eq!(candidates.len(), last.iter().map(|p| p.searched).sum::<usize>());
+      assert_eq!(matches, last.iter().map(|p| p.matched).sum::<usize>());
+
+      let best: Vec<usize> = kmerge(last.iter().map(|p| p.best.iter()))
+        .take(10)
+        .map(|r| r.candidate_index)
+        .collect();
+      assert_eq!(
+        results.iter().map(|r| r.candidate_index).collect::<Vec<_>>(),
+        best
+      );
+
+      let located = search_locate_progress(&query, &candidates, 3, parallelism, &config, |p| {
+        assert!(p.best.len() <= 3)
+      });
+      assert_eq!(3, located.len());
+    }
+  }
+
+  #[test]
+  fn search_from_progress() {
+    let candidates: Vec<String> = (0..10_000).map(|i| format!("{}/{}", i % 17, i)).collect();
+    let config = ScoringConfig::default();
+    let query = Query::new("1/2");
+    let expected = super::search_score(&query, &candidates[..100], 1, &config);
+
+    // Every thread of the search searches again as it goes
+    search_score_progress(&query, &candidates, 10, 4, &config, |_| {
+      let results = super::search_score(&query, &candidates[..100], 4, &config);
+      assert_eq!(expected, results);
+    });
+  }
 }
@@ -0,0 +1,175 @@
+use std::cell::Cell;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Mutex, OnceLock};
+use std::thread;
+
+use super::crossbeam::channel::{self, Receiver, Sender};
+
+// Work run on several threads at once, each told which worker it is
+type Task<'t> = dyn Fn(usize) + Sync + 't;
+
+thread_local! {
+  // Whether this thread is running a task, so that a task that runs another
+  // doesn't wait on pool threads that may all be waiting on it in turn
+  static IN_TASK: Cell<bool> = const { Cell::new(false) };
+}
+
+// Runs a task as one of its workers on a pool thread
+struct Job {
+  task: *const Task<'static>,
+  worker: usize,
+  // Dropped only once the job is done with the task
+  done: mpsc::Sender<thread::Result<()>>,
+}
+
+// The task is `Sync`, and outlives the job (see `Pool::run`)
+unsafe impl Send for Job {}
+
+/// Threads kept between searches, so that searching doesn't start threads
+///
+/// The pool grows to the most threads any search has needed at once. Searches
+/// run at the same time share its threads, so each only gets them once the
+/// ones before it are done with them, and a search run from within another
+/// (say, from a progress callback) runs all on the thread that starts it.
+pub(crate) struct Pool {
+  jobs: Sender<Job>,
+  waiting: Receiver<Job>,
+  threads: Mutex<usize>,
+}
+
+impl Pool {
+  fn new() -> Self {
+    let (jobs, waiting) = channel::unbounded();
+    Self {
+      jobs,
+      waiting,
+      threads: Mutex::new(0),
+    }
+  }
+
+  /// The pool shared by every search
+  pub(crate) fn global() -> &'static Pool {
+    static POOL: OnceLock<Pool> = OnceLock::new();
+    POOL.get_or_init(Pool::new)
+  }
+
+  /// Runs `task` as `parallelism` workers at once, worker 0 on the calling
+  /// thread and the others on pool threads, returning once every worker has
+  /// finished
+  ///
+  /// Pool threads that are busy with other tasks get to this one when they're
+  /// done, so `task` shouldn't wait on other workers. If this is called from
+  /// within a task, the workers all run one after another on the calling
+  /// thread instead. If `task` panics on any thread, this panics once every
+  /// worker has finished.
+  pub(crate) fn run(&self, parallelism: usize, task: &Task) {
+    if IN_TASK.with(Cell::get) {
+      let mut result = Ok(());
+      for worker in 0..parallelism {
+        result = result.and(panic::catch_unwind(AssertUnwindSafe(|| task(worker))));
+      }
+      if let Err(panic) = result {
+        panic::resume_unwind(panic);
+      }
+      return;
+    }
+    self.grow(parallelism.saturating_sub(1));
+
+    // Safe because this doesn't return until every job holding the task has
+    // been dropped, even if the task panics
+    let task_ptr: *const Task<'static> = unsafe { mem::transmute(task as *const Task) };
+    let (done, finished) = mpsc::channel();
+    for worker in 1..parallelism {
+      self.jobs.send(Job {
+        task: task_ptr,
+        worker,
+        done: done.clone(),
+      });
+    }
+    drop(done);
+
+    IN_TASK.with(|in_task| in_task.set(true));
+    let mut result = panic::catch_unwind(AssertUnwindSafe(|| task(0)));
+    IN_TASK.with(|in_task| in_task.set(false));
+    for job_result in finished.iter() {
+      result = result.and(job_result);
+    }
+    if let Err(panic) = result {
+      panic::resume_unwind(panic);
+    }
+  }
+
+  fn grow(&self, thread_count: usize) {
+    let mut threads = self.threads.lock().unwrap();
+    while *threads < thread_count {
+      let waiting = self.waiting.clone();
+      thread::spawn(move || {
+        IN_TASK.with(|in_task| in_task.set(true));
+        while let Some(job) = waiting.recv() {
+          let task = unsafe { &*job.task };
+          let result = panic::catch_unwind(AssertUnwindSafe(|| task(job.worker)));
+          let _ = job.done.send(result);
+        }
+      });
+      *threads += 1;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  #[test]
+  fn runs_every_worker() {
+    let pool = Pool::new();
+    for &parallelism in &[1, 4, 2, 8] {
+      let ran: Vec<AtomicUsize> = (0..parallelism).map(|_| AtomicUsize::new(0)).collect();
+      pool.run(parallelism, &|worker| {
+        ran[worker].fetch_add(1, Ordering::SeqCst);
+      });
+      assert!(ran.iter().all(|count| count.load(Ordering::SeqCst) == 1));
+    }
+    assert_eq!(7, *pool.threads.lock().unwrap());
+  }
+
+  #[test]
+  fn panics_once_finished() {
+    let pool = Pool::new();
+    let finished = AtomicUsize::new(0);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+      pool.run(4, &|worker| {
+        if worker == 2 {
+          panic!("worker 2");
+        }
+        finished.fetch_add(1, Ordering::SeqCst);
+      })
+    }));
+    assert!(result.is_err());
+    assert_eq!(3, finished.load(Ordering::SeqCst));
+
+    // The pool still works after a panic
+    let ran = AtomicUsize::new(0);
+    pool.run(4, &|_| {
+      ran.fetch_add(1, Ordering::SeqCst);
+    });
+    assert_eq!(4, ran.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn runs_nested_tasks() {
+    let pool = Pool::new();
+    let ran = AtomicUsize::new(0);
+    pool.run(2, &|_| {
+      // Every thread of the pool is busy with this task, so workers queued
+      // for them would never run
+      pool.run(3, &|_| {
+        ran.fetch_add(1, Ordering::SeqCst);
+      });
+    });
+    assert_eq!(6, ran.load(Ordering::SeqCst));
+    assert_eq!(1, *pool.threads.lock().unwrap());
+  }
+}
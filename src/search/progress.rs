@@ -0,0 +1,53 @@
+/// How far one thread of a search has gotten
+///
+/// The best results of the whole search so far are the best of the latest
+/// `best` from each thread.
+#[derive(Clone, Debug)]
+pub struct Progress<T> {
+  /// Which thread of the search this is, counting from 0
+  pub worker: usize,
+  /// How many candidates this thread has searched
+  pub searched: usize,
+  /// How many of those matched the query
+  pub matched: usize,
+  /// How many candidates the whole search has to search
+  pub total: usize,
+  /// This thread's best results so far (highest score first), at most as many
+  /// as the search returns
+  pub best: Vec<T>,
+}
+
+// How far a thread has gotten, with the results it's keeping in no order
+pub(crate) struct Snapshot<'s, T: 's> {
+  pub worker: usize,
+  pub searched: usize,
+  pub matched: usize,
+  pub kept: &'s [T],
+}
+
+// Called by each thread of a search with how far it has gotten
+pub(crate) type Report<'r, T> = dyn Fn(Snapshot<T>) + Sync + 'r;
+
+// Reports each snapshot to `on_progress` with the best `k` results it kept
+pub(crate) fn reporter<'p, T, P>(
+  total: usize,
+  k: usize,
+  on_progress: &'p P,
+) -> impl Fn(Snapshot<T>) + Sync + 'p
+where
+  T: Clone + Ord,
+  P: Fn(Progress<T>) + Sync,
+{
+  move |snapshot| {
+    let mut best = snapshot.kept.to_vec();
+    best.sort_unstable();
+    best.truncate(k);
+    on_progress(Progress {
+      worker: snapshot.worker,
+      searched: snapshot.searched,
+      matched: snapshot.matched,
+      total,
+      best,
+    });
+  }
+}
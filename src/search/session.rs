@@ -0,0 +1,440 @@
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use score::{Matcher, Normalization, Query, QueryOptions};
+
+use super::cancel::{is_cancelled, unless_cancelled};
+use super::index::CachedFn;
+use super::itertools::merge;
+use super::{CancellationToken, Cancelled, Index, LocateResults, ScoreResults};
+
+/// Searches an `Index` again and again as a query is typed
+///
+/// When a query extends the one before it (as when a character is typed),
+/// only the candidates that matched before are searched. The candidates that
+/// match each query are kept until the query no longer extends it, so going
+/// back to an earlier query (as when a character is deleted) doesn't search
+/// at all before scoring.
+///
+/// Candidates can be added as they arrive from a slow source without losing
+/// the candidates kept for earlier queries: between searches with `extend`,
+/// or from other threads at any time, even while searching, through the
+/// session's `Feed`.
+#[derive(Debug)]
+pub struct Session {
+  index: Index,
+  options: QueryOptions,
+  parallelism: usize,
+  // Each query searched that extends the one before, with the indices of the
+  // candidates that match it
+  history: Vec<(String, Vec<usize>)>,
+  feed: Feed,
+}
+
+/// Adds candidates to a `Session` from any thread, even while it searches
+///
+/// Candidates fed to a session are added after all the others as its search
+/// in progress finishes, and are searched before it returns, or by the next
+/// search if none is in progress.
+#[derive(Clone, Debug, Default)]
+pub struct Feed {
+  pending: Arc<Mutex<Vec<String>>>,
+}
+
+impl Feed {
+  /// Adds a candidate after all the others
+  pub fn push<S: Into<String>>(&self, candidate: S) {
+    self.pending.lock().unwrap().push(candidate.into());
+  }
+
+  /// Adds candidates after all the others
+  pub fn extend<I, S>(&self, candidates: I)
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    let mut pending = self.pending.lock().unwrap();
+    pending.extend(candidates.into_iter().map(Into::into));
+  }
+
+  /// Whether every candidate fed has been added to the session
+  pub fn is_empty(&self) -> bool {
+    self.pending.lock().unwrap().is_empty()
+  }
+
+  fn take(&self) -> Vec<String> {
+    mem::take(&mut *self.pending.lock().unwrap())
+  }
+}
+
+impl Session {
+  pub fn new(index: Index, options: &QueryOptions, parallelism: usize) -> Self {
+    Self {
+      index,
+      options: options.clone(),
+      parallelism,
+      history: Vec::new(),
+      feed: Feed::default(),
+    }
+  }
+
+  /// A handle for adding candidates to this session from other threads
+  pub fn feed(&self) -> Feed {
+    self.feed.clone()
+  }
+
+  /// The index being searched
+  pub fn index(&self) -> &Index {
+    &self.index
+  }
+
+  /// Adds candidates after all the others, to be found by the next search
+  ///
+  /// Only the new candidates are checked against the queries kept, so adding
+  /// a batch costs about as much as searching the batch.
+  pub fn extend<I, S>(&mut self, candidates: I)
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    let start = self.index.len();
+    self.index.extend(candidates);
+
+    let mut among: Vec<usize> = (start..self.index.len()).collect();
+    for (query, matches) in &mut self.history {
+      let parsed = Query::with_options(query, &self.options);
+      among = self.index.matching(&parsed, Some(&among), None);
+      matches.extend_from_slice(&among);
+    }
+  }
+
+  /// Search for `query`, returning the `k` best results (highest score first)
+  pub fn search_top(&mut self, query: &str, k: usize) -> ScoreResults {
+    self.search(query, k, Matcher::score_cached, None)
+  }
+
+  /// Search for `query`, returning the `k` best results (highest score first)
+  /// with the locations of the query in each candidate
+  pub fn locate_top(&mut self, query: &str, k: usize) -> LocateResults {
+    self.search(query, k, Matcher::locate_cached, None)
+  }
+
+  /// As `search_top`, giving up with `Cancelled` if `cancel` is cancelled
+  /// before the search finishes
+  pub fn search_top_cancellable(
+    &mut self,
+    query: &str,
+    k: usize,
+    cancel: &CancellationToken,
+  ) -> Result<ScoreResults, Cancelled> {
+    let results = self.search(query, k, Matcher::score_cached, Some(cancel));
+    unless_cancelled(results, cancel)
+  }
+
+  /// As `locate_top`, giving up with `Cancelled` if `cancel` is cancelled
+  /// before the search finishes
+  ///
+  /// The candidates matching `query` are only kept for later searches if the
+  /// search wasn't cancelled while finding them.
+  pub fn locate_top_cancellable(
+    &mut self,
+    query: &str,
+    k: usize,
+    cancel: &CancellationToken,
+  ) -> Result<LocateResults, Cancelled> {
+    let results = self.search(query, k, Matcher::locate_cached, Some(cancel));
+    unless_cancelled(results, cancel)
+  }
+
+  fn search<T>(
+    &mut self,
+    query: &str,
+    k: usize,
+    search_fn: CachedFn<T>,
+    cancel: Option<&CancellationToken>,
+  ) -> Vec<T>
+  where
+    T: Ord + Send + 'static,
+  {
+    let parsed = self.refine(query, cancel);
+    if is_cancelled(cancel) {
+      return Vec::new();
+    }
+    let matches = &self.history.last().unwrap().1;
+    let mut results = self
+      .index
+      .search_among(&parsed, matches, k, self.parallelism, search_fn, cancel);
+
+    // Candidates fed while searching are added, and those that match are
+    // searched and merged in, until no more have been fed
+    while !is_cancelled(cancel) {
+      let fed = self.feed.take();
+      if fed.is_empty() {
+        break;
+      }
+      let start = self.index.len();
+      self.extend(fed);
+      let matches = &self.history.last().unwrap().1;
+      let new = &matches[matches.partition_point(|&index| index < start)..];
+      let found = self
+        .index
+        .search_among(&parsed, new, k, self.parallelism, search_fn, cancel);
+      results = merge(results, found).take(k).collect();
+    }
+    results
+  }
+
+  /// The number of candidates that match the last query searched
+  pub fn match_count(&self) -> usize {
+    self.history.last().map_or(0, |(_, matches)| matches.len())
+  }
+
+  // Parses `query` and finds the candidates that match it, starting from the
+  // candidates that matched the last query it extends
+  fn refine(&mut self, query: &str, cancel: Option<&CancellationToken>) -> Query {
+    let parsed = Query::with_options(query, &self.options);
+    if !self.can_refine() {
+      self.history.clear();
+    }
+    while self
+      .history
+      .last()
+      .is_some_and(|(previous, _)| !query.starts_with(&previous[..]))
+    {
+      self.history.pop();
+    }
+
+    if self.history.last().is_none_or(|(previous, _)| previous != query) {
+      let among = self.history.last().map(|(_, matches)| &matches[..]);
+      let matches = self.index.matching(&parsed, among, cancel);
+      if !is_cancelled(cancel) {
+        self.history.push((query.to_string(), matches));
+      }
+    }
+    parsed
+  }
+
+  // Whether a candidate that matches a query always matches the queries it
+  // extends, which isn't true with the extended syntax (adding a `|`
+  // alternative matches more), with composing normalizations or with
+  // grapheme clusters (adding a combining mark changes the last cluster)
+  fn can_refine(&self) -> bool {
+    !self.options.extended
+      && !self.options.graphemes
+      && self.options.normalization == Normalization::None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use score::config::ScoringConfig;
+  use score::CaseMatching;
+  use std::thread;
+
+  fn candidates() -> Vec<String> {
+    (0..2_000).map(|i| format!("{}/{:x}/{}", i % 13, i * 31, i)).collect()
+  }
+
+  fn indices<R>(results: &[R], index: fn(&R) -> usize) -> Vec<usize> {
+    results.iter().map(index).collect()
+  }
+
+  #[test]
+  fn refining_gives_same_results() {
+    let config = ScoringConfig::default();
+    let mut index = Index::new(&config);
+    index.extend(candidates());
+
+    let typed = ["", "1", "12", "12/", "12/a", "12/", "12", "1", "1f", "1f3", "", "7", "7/7"];
+    for options in &[
+      QueryOptions::default(),
+      QueryOptions {
+        case: CaseMatching::Sensitive,
+        ..QueryOptions::default()
+      },
+      QueryOptions {
+        extended: true,
+        ..QueryOptions::default()
+      },
+    ] {
+      let mut session = Session::new(index.clone(), options, 4);
+      for query in &typed {
+        let parsed = Query::with_options(query, options);
+        let expected = index.locate_top(&parsed, 20, 4);
+        let results = session.locate_top(query, 20);
+        assert_eq!(
+          indices(&expected, |r| r.candidate_index),
+          indices(&results, |r| r.candidate_index),
+          "{:?}",
+          query
+        );
+        for (expected, result) in expected.iter().zip(&results) {
+          assert_eq!(expected.match_mask, result.match_mask);
+        }
+        assert_eq!(index.search(&parsed, 1).len(), session.match_count());
+
+        let expected = index.search_top(&parsed, 20, 1);
+        let results = session.search_top(query, 20);
+        assert_eq!(
+          indices(&expected, |r| r.candidate_index),
+          indices(&results, |r| r.candidate_index)
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn history() {
+    let config = ScoringConfig::default();
+    let mut index = Index::new(&config);
+    index.extend(candidates());
+    let mut session = Session::new(index.clone(), &QueryOptions::default(), 1);
+
+    session.search_top("1", 1);
+    session.search_top("12", 1);
+    session.search_top("123", 1);
+    assert_eq!(3, session.history.len());
+
+    // Going back keeps the earlier match sets
+    session.search_top("12", 1);
+    assert_eq!(2, session.history.len());
+    session.search_top("13", 1);
+    assert_eq!(
+      vec!["1", "13"],
+      session.history.iter().map(|h| &h.0[..]).collect::<Vec<_>>()
+    );
+    session.search_top("x", 1);
+    assert_eq!(1, session.history.len());
+    assert_eq!(0, session.match_count());
+  }
+
+  #[test]
+  fn extended_queries_are_searched_in_full() {
+    let config = ScoringConfig::default();
+    let mut index = Index::new(&config);
+    index.extend(vec!["apple", "banana"]);
+    let options = QueryOptions {
+      extended: true,
+      ..QueryOptions::default()
+    };
+    let mut session = Session::new(index.clone(), &options, 1);
+    assert_eq!(1, session.search_top("app", 10).len());
+    assert_eq!(1, session.search_top("app |", 10).len());
+    assert_eq!(2, session.search_top("app | ban", 10).len());
+  }
+
+  #[test]
+  fn cancelled_searches_are_forgotten() {
+    let config = ScoringConfig::default();
+    let mut index = Index::new(&config);
+    index.extend(candidates());
+    let mut session = Session::new(index.clone(), &QueryOptions::default(), 2);
+
+    let token = CancellationToken::new();
+    let expected = session.locate_top("1", 10);
+    assert_eq!(
+      indices(&expected, |r| r.candidate_index),
+      indices(&session.locate_top_cancellable("1", 10, &token).unwrap(), |r| {
+        r.candidate_index
+      })
+    );
+
+    token.cancel();
+    assert_eq!(Err(Cancelled), session.search_top_cancellable("12", 10, &token));
+    assert!(session.locate_top_cancellable("12", 10, &token).is_err());
+    assert_eq!(1, session.history.len());
+
+    // The next search starts from the last one that finished
+    let expected = index.search_top(&Query::new("12"), 10, 1);
+    assert_eq!(
+      indices(&expected, |r| r.candidate_index),
+      indices(&session.search_top("12", 10), |r| r.candidate_index)
+    );
+    assert_eq!(2, session.history.len());
+  }
+
+  #[test]
+  fn extending_gives_same_results() {
+    let config = ScoringConfig::default();
+    let mut index = Index::new(&config);
+    index.extend(candidates());
+    let all = candidates();
+    let (first, rest) = all.split_at(500);
+    let mut session = Session::new(Index::new(&config), &QueryOptions::default(), 4);
+    session.extend(first.to_vec());
+
+    session.search_top("1", 10);
+    session.search_top("12", 10);
+    for batch in rest.chunks(300) {
+      session.extend(batch.to_vec());
+      session.search_top("12", 10);
+    }
+    assert_eq!(index.len(), session.index().len());
+    assert_eq!(2, session.history.len());
+
+    for query in &["12", "1", "12a", "3"] {
+      let expected = index.locate_top(&Query::new(query), 20, 1);
+      let results = session.locate_top(query, 20);
+      assert_eq!(
+        indices(&expected, |r| r.candidate_index),
+        indices(&results, |r| r.candidate_index),
+        "{:?}",
+        query
+      );
+      assert_eq!(index.search(&Query::new(query), 1).len(), session.match_count());
+    }
+  }
+
+  #[test]
+  fn fed_candidates_are_searched() {
+    let config = ScoringConfig::default();
+    let mut index = Index::new(&config);
+    index.extend(candidates());
+    let all = candidates();
+    let (first, rest) = all.split_at(500);
+    let mut session = Session::new(Index::new(&config), &QueryOptions::default(), 2);
+    session.extend(first.to_vec());
+    session.search_top("1", 10);
+
+    // Fed before a search, they're merged into its results
+    let feed = session.feed();
+    feed.extend(rest[..700].to_vec());
+    assert!(!feed.is_empty());
+    let results = session.search_top("12", 1_000);
+    assert!(feed.is_empty());
+    assert_eq!(1_200, session.index().len());
+    assert!(results.iter().any(|r| r.candidate_index >= 500));
+    let expected = session.index().search_top(&Query::new("12"), 1_000, 1);
+    assert_eq!(
+      indices(&expected, |r| r.candidate_index),
+      indices(&results, |r| r.candidate_index)
+    );
+    assert_eq!(2, session.history.len());
+
+    // Fed from another thread while searching
+    let rest = rest[700..].to_vec();
+    let feeding = thread::spawn(move || {
+      for candidate in rest {
+        feed.push(candidate);
+      }
+    });
+    let queries = ["1", "12", "1", "3", "3a"];
+    for query in queries.iter().cycle().take(200) {
+      session.locate_top(query, 10);
+    }
+    feeding.join().unwrap();
+
+    for query in &["12", "1", "3a", "7/"] {
+      let expected = index.locate_top(&Query::new(query), 20, 1);
+      let results = session.locate_top(query, 20);
+      assert_eq!(
+        indices(&expected, |r| r.candidate_index),
+        indices(&results, |r| r.candidate_index),
+        "{:?}",
+        query
+      );
+      assert_eq!(index.search(&Query::new(query), 1).len(), session.match_count());
+    }
+  }
+}
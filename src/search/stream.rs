@@ -0,0 +1,350 @@
+extern crate futures;
+
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::thread;
+
+use self::futures::channel::oneshot;
+use self::futures::Stream;
+
+use super::crossbeam::channel::{self, Receiver, Sender};
+
+use score::config::ScoringConfig;
+use score::{LocateResult, Matcher, Query, ScoreResult};
+
+use super::{as_str, by_key, search_internal, CancellationToken, Hooks};
+
+// How many results are taken at once
+const BATCH_LEN: usize = 64;
+
+type Results<T> = Box<dyn Iterator<Item = T> + Send>;
+
+/// Batches of results (highest score first) from a search running on another
+/// thread
+///
+/// The search runs on its own thread, and its results are put in order a
+/// batch at a time as they're taken from the stream. If the search panics,
+/// the stream ends with a `Panicked` error rather than a batch.
+///
+/// Dropping the stream cancels the search, and results that are never taken
+/// are never put in order.
+pub struct SearchStream<T> {
+  searching: Option<oneshot::Receiver<thread::Result<Results<T>>>>,
+  results: Option<Results<T>>,
+  cancel: CancellationToken,
+}
+
+impl<T> Stream for SearchStream<T> {
+  type Item = Result<Vec<T>, Panicked>;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+    let this = self.get_mut();
+    if let Some(ref mut searching) = this.searching {
+      let searched = match Pin::new(searching).poll(cx) {
+        Poll::Ready(searched) => searched,
+        Poll::Pending => return Poll::Pending,
+      };
+      this.searching = None;
+      match searched {
+        Ok(Ok(results)) => this.results = Some(results),
+        // The search panicked, or its thread did before sending anything
+        Ok(Err(_)) | Err(_) => return Poll::Ready(Some(Err(Panicked))),
+      }
+    }
+
+    let batch: Vec<T> = match this.results {
+      Some(ref mut results) => results.take(BATCH_LEN).collect(),
+      None => return Poll::Ready(None),
+    };
+    if batch.is_empty() {
+      this.results = None;
+      Poll::Ready(None)
+    } else {
+      Poll::Ready(Some(Ok(batch)))
+    }
+  }
+}
+
+impl<T> fmt::Debug for SearchStream<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("SearchStream")
+      .field("searching", &self.searching.is_some())
+      .field("cancel", &self.cancel)
+      .finish_non_exhaustive()
+  }
+}
+
+impl<T> Drop for SearchStream<T> {
+  fn drop(&mut self) {
+    self.cancel.cancel();
+  }
+}
+
+/// The error ending a `SearchStream` whose search panicked
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Panicked;
+
+impl fmt::Display for Panicked {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "search panicked")
+  }
+}
+
+impl Error for Panicked {}
+
+/// As `search_score_iter`, searching on a thread kept for searches rather than
+/// the calling one, so that async code isn't held up
+///
+/// The candidates are moved to the search thread, so candidates shared with
+/// other code can be given as an `Arc<[S]>`. At most as many searches as
+/// there are CPUs run on those threads at once, and the others wait for one
+/// to finish.
+pub fn search_score_stream<S, A>(
+  query: &Query,
+  candidates: A,
+  parallelism: usize,
+  config: &ScoringConfig,
+) -> SearchStream<ScoreResult>
+where
+  S: AsRef<str> + Sync + 'static,
+  A: AsRef<[S]> + Send + 'static,
+{
+  stream(
+    query,
+    candidates,
+    parallelism,
+    config,
+    Matcher::score_indexed,
+  )
+}
+
+/// As `search_locate_iter`, searching on a thread kept for searches rather
+/// than the calling one, so that async code isn't held up
+///
+/// See `search_score_stream`.
+pub fn search_locate_stream<S, A>(
+  query: &Query,
+  candidates: A,
+  parallelism: usize,
+  config: &ScoringConfig,
+) -> SearchStream<LocateResult>
+where
+  S: AsRef<str> + Sync + 'static,
+  A: AsRef<[S]> + Send + 'static,
+{
+  stream(
+    query,
+    candidates,
+    parallelism,
+    config,
+    Matcher::locate_indexed,
+  )
+}
+
+fn stream<S, A, T>(
+  query: &Query,
+  candidates: A,
+  parallelism: usize,
+  config: &ScoringConfig,
+  search_fn: fn(&mut Matcher, &str, usize) -> T,
+) -> SearchStream<T>
+where
+  S: AsRef<str> + Sync + 'static,
+  A: AsRef<[S]> + Send + 'static,
+  T: Ord + Send + 'static,
+{
+  let (searched, searching) = oneshot::channel();
+  let cancel = CancellationToken::new();
+  let query = query.clone();
+  let config = config.clone();
+  let token = cancel.clone();
+  spawn_blocking(Box::new(move || {
+    let results = panic::catch_unwind(AssertUnwindSafe(|| {
+      let search = by_key(&query, &as_str, search_fn);
+      let hooks = Hooks::cancel(&token);
+      search_internal(
+        &query,
+        candidates.as_ref(),
+        usize::MAX,
+        parallelism,
+        &config,
+        &search,
+        &hooks,
+      )
+    }));
+    // Fails only once the stream is dropped
+    let _ = searched.send(results);
+  }));
+  SearchStream {
+    searching: Some(searching),
+    results: None,
+    cancel,
+  }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// Threads that searches for streams run on, started as they're needed up to
+// one for each CPU, and kept once they're idle
+struct BlockingPool {
+  jobs: Sender<Job>,
+  waiting: Receiver<Job>,
+  // Jobs sent that haven't finished
+  pending: AtomicUsize,
+  threads: Mutex<usize>,
+  max_threads: usize,
+}
+
+fn blocking_pool() -> &'static BlockingPool {
+  static POOL: OnceLock<BlockingPool> = OnceLock::new();
+  POOL.get_or_init(|| {
+    let (jobs, waiting) = channel::unbounded();
+    BlockingPool {
+      jobs,
+      waiting,
+      pending: AtomicUsize::new(0),
+      threads: Mutex::new(0),
+      max_threads: thread::available_parallelism().map_or(4, |n| n.get()),
+    }
+  })
+}
+
+fn spawn_blocking(job: Job) {
+  let pool = blocking_pool();
+
+  // Enough threads for every pending job, up to the most allowed. Jobs don't
+  // wait on the streams they're for, so those beyond that are always taken
+  // once a thread is done with another.
+  let pending = pool.pending.fetch_add(1, Ordering::SeqCst) + 1;
+  let mut threads = pool.threads.lock().unwrap();
+  if *threads < pending.min(pool.max_threads) {
+    let waiting = pool.waiting.clone();
+    thread::spawn(move || {
+      while let Some(job) = waiting.recv() {
+        // Jobs send a panicking search to its stream, so this only keeps the
+        // thread going if anything else panics
+        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+        blocking_pool().pending.fetch_sub(1, Ordering::SeqCst);
+      }
+    });
+    *threads += 1;
+  }
+  drop(threads);
+  pool.jobs.send(job);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use search::{search_locate, search_score};
+  use std::sync::Arc;
+
+  use self::futures::executor::block_on;
+  use self::futures::StreamExt;
+
+  fn candidates() -> Vec<String> {
+    (0..5_000).map(|i| format!("{}/{}", i % 17, i)).collect()
+  }
+
+  #[test]
+  fn same_as_searching() {
+    let candidates = candidates();
+    let shared: Arc<[String]> = candidates.clone().into();
+    let config = ScoringConfig::default();
+
+    for parallelism in 1..4 {
+      for query in &["", "1", "1/2", "nothing"] {
+        let query = Query::new(query);
+        let expected = search_score(&query, &candidates, parallelism, &config);
+        let stream = search_score_stream(&query, shared.clone(), parallelism, &config);
+        let batches: Vec<Vec<ScoreResult>> = block_on(stream.map(Result::unwrap).collect());
+        assert!(batches.iter().all(|batch| batch.len() <= BATCH_LEN));
+        assert_eq!(
+          expected
+            .iter()
+            .map(|r| r.candidate_index)
+            .collect::<Vec<_>>(),
+          batches
+            .concat()
+            .iter()
+            .map(|r| r.candidate_index)
+            .collect::<Vec<_>>()
+        );
+
+        let expected = search_locate(&query, &candidates, parallelism, &config);
+        let stream = search_locate_stream(&query, candidates.clone(), parallelism, &config);
+        let results = block_on(stream.map(Result::unwrap).concat());
+        assert_eq!(expected.len(), results.len());
+        for (expected, result) in expected.iter().zip(&results) {
+          assert_eq!(expected.candidate_index, result.candidate_index);
+          assert_eq!(expected.match_mask, result.match_mask);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn cancelled_on_drop() {
+    let config = ScoringConfig::default();
+    let mut stream = search_score_stream(&Query::new("1"), candidates(), 2, &config);
+    let cancel = stream.cancel.clone();
+    let first = block_on(stream.next()).unwrap().unwrap();
+    assert_eq!(BATCH_LEN, first.len());
+    assert!(!cancel.is_cancelled());
+
+    drop(stream);
+    assert!(cancel.is_cancelled());
+  }
+
+  #[test]
+  fn reports_panics() {
+    let config = ScoringConfig::default();
+    let panicking: fn(&mut Matcher, &str, usize) -> ScoreResult = |_, _, index| {
+      if index == 1_000 {
+        panic!("search panicked");
+      }
+      ScoreResult::new(index)
+    };
+    for parallelism in 1..4 {
+      let stream = stream(
+        &Query::new("1"),
+        candidates(),
+        parallelism,
+        &config,
+        panicking,
+      );
+      let items: Vec<Result<Vec<ScoreResult>, Panicked>> = block_on(stream.collect());
+      assert!(matches!(items[..], [Err(Panicked)]), "{:?}", items);
+    }
+
+    // Streams still search after a panic
+    let stream = search_score_stream(&Query::new("1"), candidates(), 2, &config);
+    assert!(block_on(stream.map(Result::unwrap).concat()).len() > BATCH_LEN);
+  }
+
+  #[test]
+  fn threads_capped() {
+    let config = ScoringConfig::default();
+    let pool = blocking_pool();
+    let streams: Vec<_> = (0..pool.max_threads * 3)
+      .map(|_| search_score_stream(&Query::new("1/2"), candidates(), 1, &config))
+      .collect();
+    assert!(*pool.threads.lock().unwrap() <= pool.max_threads);
+
+    // Streams taken last first still finish, even though their searches
+    // waited for the earlier ones
+    let expected = search_score(&Query::new("1/2"), &candidates(), 1, &config).len();
+    for stream in streams.into_iter().rev() {
+      assert_eq!(
+        expected,
+        block_on(stream.map(Result::unwrap).concat()).len()
+      );
+    }
+  }
+}